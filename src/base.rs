@@ -5,10 +5,13 @@ use vulkano::device::physical::PhysicalDeviceType;
 use vulkano::device::{
 	Device, DeviceCreateInfo, DeviceExtensions, Features, QueueCreateInfo,
 };
+use vulkano::format::Format;
 use vulkano::image::ImageUsage;
 use vulkano::instance::{Instance, InstanceCreateInfo, InstanceExtensions};
 use vulkano::memory::allocator::StandardMemoryAllocator;
-use vulkano::swapchain::{PresentMode, Swapchain, SwapchainCreateInfo};
+use vulkano::swapchain::{
+	ColorSpace, PresentMode, Swapchain, SwapchainCreateInfo,
+};
 use vulkano::{Version, VulkanLibrary};
 use vulkano_win::VkSurfaceBuild;
 use winit::dpi::{LogicalSize, Size};
@@ -30,12 +33,13 @@ pub struct Base {
 	pub comalloc: VkwComAlloc,
 }
 
-fn winit_size(size: [u32; 2]) -> Size {
+pub fn winit_size(size: [u32; 2]) -> Size {
 	Size::new(LogicalSize::new(size[0], size[1]))
 }
 
 impl Base {
-	pub fn new<E>(el: &EventLoopWindowTarget<E>) -> Self {
+	// hdr: prefer an hdr swapchain when the surface offers one
+	pub fn new<E>(el: &EventLoopWindowTarget<E>, hdr: bool) -> Self {
 		let library = VulkanLibrary::new().unwrap();
 		assert!(library.api_version() >= Version::V1_2);
 		let required_extensions = vulkano_win::required_extensions(&library);
@@ -43,12 +47,20 @@ impl Base {
 			ext_debug_utils: true,
 			..InstanceExtensions::empty()
 		};
+		// needed for non srgb color spaces
+		let optional_extensions = library.supported_extensions().intersection(
+			&InstanceExtensions {
+				ext_swapchain_colorspace: true,
+				..InstanceExtensions::empty()
+			},
+		);
 
 		let layers = vec!["VK_LAYER_KHRONOS_validation".to_owned()];
 		let instance = Instance::new(
 			library,
 			InstanceCreateInfo {
-				enabled_extensions: required_extensions & extensions,
+				enabled_extensions: (required_extensions & extensions)
+					| optional_extensions,
 				enabled_layers: layers,
 				..Default::default()
 			},
@@ -67,6 +79,7 @@ impl Base {
 			physical_device,
 			device.clone(),
 			surface.clone(),
+			hdr,
 		);
 		let (memalloc, dstalloc, comalloc) = get_allocators(device.clone());
 		Self {
@@ -107,6 +120,13 @@ pub fn get_device_and_queue(
 		runtime_descriptor_array: true,
 		..Features::empty()
 	};
+	// enabled when supported
+	let optional_features = Features {
+		wide_lines: true,
+		large_points: true,
+		fill_mode_non_solid: true,
+		..Features::empty()
+	};
 
 	let (physical_device, queue_family_index) = instance
 		.enumerate_physical_devices()
@@ -141,6 +161,11 @@ pub fn get_device_and_queue(
 	// 	physical_device.properties().device_type,
 	// );
 
+	let features = features.union(
+		&physical_device
+			.supported_features()
+			.intersection(&optional_features),
+	);
 	let (device, mut queues) = Device::new(
 		physical_device.clone(),
 		DeviceCreateInfo {
@@ -164,15 +189,23 @@ pub fn get_swapchain_and_images(
 	physical_device: VkwPhysicalDevice,
 	device: VkwDevice,
 	surface: VkwSurface,
+	hdr: bool,
 ) -> (VkwSwapchain, VkwImages) {
 	let caps = physical_device
 		.surface_capabilities(&surface, Default::default())
 		.unwrap();
 	let composite_alpha = caps.supported_composite_alpha.iter().next().unwrap();
-	let format = physical_device
+	let formats = physical_device
 		.surface_formats(&surface, Default::default())
-		.unwrap()[0]
-		.0;
+		.unwrap();
+	let hdr_formats = [
+		(Format::R16G16B16A16_SFLOAT, ColorSpace::ExtendedSrgbLinear),
+		(Format::A2B10G10R10_UNORM_PACK32, ColorSpace::Hdr10St2084),
+	];
+	let (format, color_space) = hdr_formats
+		.into_iter()
+		.find(|x| hdr && formats.contains(x))
+		.unwrap_or(formats[0]);
 	let format = Some(format);
 	let window = surface.object().unwrap().downcast_ref::<Window>().unwrap();
 	let dimensions: [u32; 2] = window.inner_size().into();
@@ -183,9 +216,12 @@ pub fn get_swapchain_and_images(
 		SwapchainCreateInfo {
 			min_image_count: caps.min_image_count,
 			image_format: format,
+			image_color_space: color_space,
 			image_extent: dimensions,
 			image_usage: ImageUsage {
 				color_attachment: true,
+				// for recording
+				transfer_src: caps.supported_usage_flags.transfer_src,
 				..ImageUsage::empty()
 			},
 			composite_alpha,
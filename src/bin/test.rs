@@ -1,4 +1,4 @@
-use winit::event::{ElementState, Event, WindowEvent};
+use winit::event::{Event, WindowEvent};
 use winit::event_loop::{ControlFlow, EventLoop};
 
 use triangles::bmtext::FontConfig;
@@ -12,10 +12,12 @@ fn main() {
 	let el = EventLoop::new();
 	let mut rdr = Renderer::new(&el);
 	let ssize = rdr.get_size();
+	let scale_factor = rdr.get_scale_factor();
 
 	// draw text
 	let img = Teximg::load("../fontdata/v1/unifont1.png");
-	let fc = FontConfig::new(ssize, img.dim, [16, 16]);
+	let mut fc = FontConfig::new(ssize, img.dim, [16, 16])
+		.with_scale_factor(scale_factor);
 	rdr.upload_tex(img, 0);
 	let mut model = fc.generate_model();
 	model.faces = Vec::new();
@@ -47,11 +49,13 @@ fn main() {
 			color: [0.5, 0.0, 0.0, 1.0],
 			layer: -1, // no texture
 			uvid: [0; 3],
+			..Default::default()
 		}],
+		..Default::default()
 	};
 	let _triangle_model = rdr.insert_model(&model);
 
-	let mut camcon = Camcon::new(ssize);
+	let mut camcon = Camcon::new(ssize).with_scale_factor(scale_factor);
 	let mut dirty = false;
 
 	// event loop
@@ -67,12 +71,13 @@ fn main() {
 				WindowEvent::Resized(_) => {
 					let ssize = rdr.get_size();
 					camcon.resize(ssize);
+					fc.resize_screen(ssize);
 					rdr.damage();
 				}
-				WindowEvent::KeyboardInput { input, .. } => {
-					if input.state == ElementState::Pressed {
-						dirty = true;
-					}
+				// camcon handles it in process_event
+				WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+					fc.set_scale_factor(scale_factor);
+					rdr.damage();
 				}
 				_ => {}
 			}
@@ -81,7 +86,8 @@ fn main() {
 			rdr.render(camcon.get_camera());
 		}
 		Event::MainEventsCleared => {
-			if dirty {
+			// camera changes are only known to camcon
+			if dirty || rdr.needs_redraw() {
 				dirty = false;
 				rdr.redraw();
 			}
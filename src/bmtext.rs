@@ -7,8 +7,10 @@ pub fn wide_test(ch: char) -> bool {
 	}
 }
 
+// layout is in logical pixels, screen_size is physical
 pub struct FontConfig {
 	scaler: i32,
+	scale_factor: f64,
 	font_size: [u32; 2],
 	screen_size: [u32; 2],
 	texture_size: [u32; 2],
@@ -22,6 +24,7 @@ impl FontConfig {
 	) -> Self {
 		Self {
 			scaler: 1,
+			scale_factor: 1.0,
 			font_size,
 			texture_size,
 			screen_size,
@@ -33,6 +36,22 @@ impl FontConfig {
 		self
 	}
 
+	pub fn with_scale_factor(mut self, scale_factor: f64) -> Self {
+		self.scale_factor = scale_factor;
+		self
+	}
+
+	pub fn set_scale_factor(&mut self, scale_factor: f64) {
+		self.scale_factor = scale_factor;
+	}
+
+	fn get_logical_screen_size(&self) -> [u32; 2] {
+		[
+			(self.screen_size[0] as f64 / self.scale_factor) as u32,
+			(self.screen_size[1] as f64 / self.scale_factor) as u32,
+		]
+	}
+
 	pub fn resize_screen(&mut self, new_size: [u32; 2]) {
 		self.screen_size = new_size;
 		// eprintln!("FontConfig resized: {:?}", new_size);
@@ -86,14 +105,15 @@ impl FontConfig {
 		Model {
 			vs,
 			uvs,
-			faces: Default::default(),
+			..Default::default()
 		}
 	}
 
 	pub fn get_terminal_size_in_char(&self) -> [u32; 2] {
+		let screen_size = self.get_logical_screen_size();
 		[
-			self.screen_size[0] / (self.font_size[0] / 2 * self.scaler as u32),
-			self.screen_size[1] / (self.font_size[1] * self.scaler as u32),
+			screen_size[0] / (self.font_size[0] / 2 * self.scaler as u32),
+			screen_size[1] / (self.font_size[1] * self.scaler as u32),
 		]
 	}
 
@@ -147,12 +167,14 @@ impl FontConfig {
 				color,
 				layer,
 				uvid: [texture_leftup, texture_leftup + n, texture_leftdown],
+				..Default::default()
 			};
 			let face2 = Face {
 				vid: [screen_leftup + n, screen_leftdown, screen_leftdown + n],
 				color,
 				layer,
 				uvid: [texture_leftup + n, texture_leftdown, texture_leftdown + n],
+				..Default::default()
 			};
 			result.push(face1);
 			result.push(face2);
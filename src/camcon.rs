@@ -3,10 +3,12 @@ use winit::event::{ElementState, MouseButton, WindowEvent};
 type V2 = nalgebra::Vector2<f32>;
 
 // 2d camera controller
+// screen is in physical pixels, world unit is a logical pixel at zoom 1
 pub struct Camcon {
 	world_center: V2,
 	screen_r: V2, // e.g. (960.0, 540.0)
 	zoom: f32,
+	scale_factor: f32,
 
 	control_state: ControlState,
 }
@@ -25,17 +27,33 @@ impl Camcon {
 			world_center: r,
 			screen_r: r,
 			zoom: 1.0,
+			scale_factor: 1.0,
 
 			control_state: Default::default(),
 		}
 	}
 
+	pub fn with_scale_factor(mut self, scale_factor: f64) -> Self {
+		self.scale_factor = scale_factor as f32;
+		self.world_center = self.screen_r / self.scale_factor;
+		self
+	}
+
+	pub fn set_scale_factor(&mut self, scale_factor: f64) {
+		self.scale_factor = scale_factor as f32;
+	}
+
+	// physical pixels per world unit
+	fn pixel_ratio(&self) -> f32 {
+		self.zoom * self.scale_factor
+	}
+
 	pub fn move_view(&mut self, ds: V2) {
-		self.world_center -= ds / self.zoom;
+		self.world_center -= ds / self.pixel_ratio();
 	}
 
 	pub fn s2w(&self, pos: V2) -> V2 {
-		let result = (pos - self.screen_r) / self.zoom;
+		let result = (pos - self.screen_r) / self.pixel_ratio();
 		result + self.world_center
 	}
 
@@ -50,8 +68,8 @@ impl Camcon {
 
 	pub fn get_camera(&self) -> crate::M4 {
 		let [cx, cy]: [f32; 2] = self.world_center.into();
-		let [rx, ry]: [f32; 2] =
-			[self.screen_r[0] / self.zoom, self.screen_r[1] / self.zoom];
+		let k = self.pixel_ratio();
+		let [rx, ry]: [f32; 2] = [self.screen_r[0] / k, self.screen_r[1] / k];
 		crate::M4::new_orthographic(
 			cx - rx,
 			cx + rx,
@@ -83,15 +101,25 @@ impl Camcon {
 				}
 				self.control_state.prev_cursor_pos = Some(pos);
 			}
-			WindowEvent::MouseInput { state, button, .. } => {
-				if *button == MouseButton::Middle {
-					self.control_state.move_button =
-						*state == ElementState::Pressed;
-				}
+			WindowEvent::MouseInput {
+				state,
+				button: MouseButton::Middle,
+				..
+			} => {
+				self.control_state.move_button =
+					*state == ElementState::Pressed;
 			}
 			WindowEvent::ModifiersChanged(state) => {
 				self.control_state.zoom_button = state.ctrl();
 			}
+			WindowEvent::ScaleFactorChanged {
+				scale_factor,
+				new_inner_size,
+			} => {
+				self.set_scale_factor(*scale_factor);
+				self.resize((**new_inner_size).into());
+				result = true;
+			}
 			_ => {}
 		}
 		result
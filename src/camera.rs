@@ -4,4 +4,5 @@ use bytemuck::{Pod, Zeroable};
 #[derive(Pod, Zeroable, Debug, Clone, Copy)]
 pub struct Camera {
 	pub data: [[f32; 4]; 4],
+	pub point_size: f32,
 }
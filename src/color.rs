@@ -0,0 +1,38 @@
+// how vertex colors are interpreted
+// 8 bit textures are srgb, float textures are linear
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WorkingSpace {
+	Linear,
+	Srgb,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ColorConfig {
+	pub working_space: WorkingSpace,
+	pub exposure: f32,
+	// reinhard, for hdr content on sdr output
+	pub tonemap: bool,
+}
+
+impl Default for ColorConfig {
+	fn default() -> Self {
+		Self {
+			working_space: WorkingSpace::Linear,
+			exposure: 1.0,
+			tonemap: false,
+		}
+	}
+}
+
+impl ColorConfig {
+	// output_params in frag.glsl and output.glsl
+	pub(crate) fn push_data(&self, pq: bool) -> [f32; 4] {
+		let flag = |x: bool| if x { 1.0 } else { 0.0 };
+		[
+			self.exposure,
+			flag(self.tonemap),
+			flag(self.working_space == WorkingSpace::Srgb),
+			flag(pq),
+		]
+	}
+}
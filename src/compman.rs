@@ -0,0 +1,194 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
+use vulkano::pipeline::{ComputePipeline, Pipeline, PipelineBindPoint};
+use vulkano::shader::ShaderModule;
+
+use crate::helper::*;
+use crate::model::modelman::{Modelman, VertexTexBuffer};
+use crate::pool::AllocStats;
+use crate::texman::Texman;
+
+// set = 0, binding = index in the binding list
+pub enum ComputeBinding {
+	// modelman vertex buffer, read write
+	Vertices,
+	// texture created by Renderer::create_storage_tex, read write
+	Texture(i32),
+}
+
+#[derive(Clone)]
+enum Resource {
+	Buffer(VertexTexBuffer),
+	Image(VkwStorageView),
+}
+
+impl PartialEq for Resource {
+	fn eq(&self, other: &Self) -> bool {
+		match (self, other) {
+			(Self::Buffer(x), Self::Buffer(y)) => Arc::ptr_eq(x, y),
+			(Self::Image(x), Self::Image(y)) => Arc::ptr_eq(x, y),
+			_ => false,
+		}
+	}
+}
+
+struct ComputePass {
+	pipeline: Arc<ComputePipeline>,
+	bindings: Vec<ComputeBinding>,
+	groups: [u32; 3],
+	// rebuilt when a bound resource is replaced
+	cached: Option<(Vec<Resource>, VkwDescriptorSet)>,
+}
+
+// like materman, pass id is chosen by user, dispatched in id order
+#[derive(Default)]
+pub struct Compman {
+	passes: BTreeMap<i32, ComputePass>,
+	frame: u32,
+}
+
+impl Compman {
+	// safety: words must be valid spir-v, see Renderer::register_compute_spirv
+	pub(crate) unsafe fn register_spirv(
+		&mut self,
+		device: VkwDevice,
+		id: i32,
+		words: &[u32],
+		bindings: Vec<ComputeBinding>,
+		groups: [u32; 3],
+	) -> Result<(), String> {
+		let module = ShaderModule::from_words(device.clone(), words)
+			.map_err(|e| format!("{}", e))?;
+		let entry_point = module
+			.entry_point("main")
+			.ok_or_else(|| "no main entry point".to_string())?;
+		let pipeline =
+			ComputePipeline::new(device, entry_point, &(), None, |_| {})
+				.map_err(|e| format!("{}", e))?;
+		for range in pipeline.layout().push_constant_ranges() {
+			if range.offset + range.size > 8 {
+				return Err("push constant block exceeds 8 bytes".to_string());
+			}
+		}
+		self.passes.insert(
+			id,
+			ComputePass {
+				pipeline,
+				bindings,
+				groups,
+				cached: None,
+			},
+		);
+		Ok(())
+	}
+
+	pub(crate) fn set_groups(&mut self, id: i32, groups: [u32; 3]) {
+		if let Some(pass) = self.passes.get_mut(&id) {
+			pass.groups = groups;
+		}
+	}
+
+	pub(crate) fn remove(&mut self, id: i32) {
+		self.passes.remove(&id);
+	}
+
+	pub(crate) fn is_empty(&self) -> bool {
+		self.passes.is_empty()
+	}
+
+	// recorded before the render pass, vulkano inserts the barriers
+	// between these writes and later vertex or sampler reads
+	pub(crate) fn dispatch(
+		&mut self,
+		builder: &mut VkwCommandBuilder,
+		modelman: &Modelman,
+		texman: &Texman,
+		dstalloc: &VkwDstAlloc,
+		stats: &mut AllocStats,
+	) {
+		let push_data = [modelman.vertex_count() as u32, self.frame];
+		self.frame = self.frame.wrapping_add(1);
+		for (id, pass) in self.passes.iter_mut() {
+			// skipped until its textures exist
+			let resources: Option<Vec<_>> = pass
+				.bindings
+				.iter()
+				.map(|binding| match binding {
+					ComputeBinding::Vertices => {
+						Some(Resource::Buffer(modelman.buffer.clone()))
+					}
+					ComputeBinding::Texture(tex) => {
+						texman.get_storage(*tex).map(Resource::Image)
+					}
+				})
+				.collect();
+			let resources = match resources {
+				Some(x) => x,
+				None => continue,
+			};
+			let layout = pass.pipeline.layout().clone();
+			builder.bind_pipeline_compute(pass.pipeline.clone());
+			if !resources.is_empty() {
+				let set = match pass.cached.as_ref() {
+					Some((cached, set)) if *cached == resources => set.clone(),
+					_ => {
+						let writes = resources.iter().enumerate().map(
+							|(idx, resource)| match resource {
+								Resource::Buffer(x) => {
+									WriteDescriptorSet::buffer(
+										idx as u32,
+										x.clone(),
+									)
+								}
+								Resource::Image(x) => {
+									WriteDescriptorSet::image_view(
+										idx as u32,
+										x.clone(),
+									)
+								}
+							},
+						);
+						let set = match layout
+							.set_layouts()
+							.first()
+							.ok_or_else(|| "no descriptor set".to_string())
+							.and_then(|set_layout| {
+								PersistentDescriptorSet::new(
+									dstalloc,
+									set_layout.clone(),
+									writes,
+								)
+								.map_err(|e| format!("{}", e))
+							}) {
+							Ok(x) => x,
+							Err(e) => {
+								eprintln!("ERROR: compute {}: {}", id, e);
+								continue;
+							}
+						};
+						stats.descriptor_sets += 1;
+						pass.cached = Some((resources, set.clone()));
+						set
+					}
+				};
+				builder.bind_descriptor_sets(
+					PipelineBindPoint::Compute,
+					layout.clone(),
+					0,
+					set,
+				);
+			}
+			// the block may declare only some of the words
+			for range in layout.push_constant_ranges() {
+				for idx in range.offset / 4..(range.offset + range.size) / 4 {
+					let data = push_data[idx as usize];
+					builder.push_constants(layout.clone(), idx * 4, data);
+				}
+			}
+			if let Err(e) = builder.dispatch(pass.groups) {
+				eprintln!("ERROR: compute {}: {}", id, e);
+			}
+		}
+	}
+}
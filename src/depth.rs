@@ -0,0 +1,68 @@
+use vulkano::format::Format;
+
+use crate::M4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DepthFormat {
+	D16,
+	// falls back to D16 if not supported
+	D32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DepthConfig {
+	pub format: DepthFormat,
+	// clear to 0 and pass greater, for use with perspective(.., true)
+	pub reverse_z: bool,
+}
+
+impl Default for DepthConfig {
+	fn default() -> Self {
+		Self {
+			format: DepthFormat::D16,
+			reverse_z: false,
+		}
+	}
+}
+
+impl DepthConfig {
+	pub(crate) fn vk_format(&self) -> Format {
+		match self.format {
+			DepthFormat::D16 => Format::D16_UNORM,
+			DepthFormat::D32 => Format::D32_SFLOAT,
+		}
+	}
+
+	pub(crate) fn clear_value(&self) -> f32 {
+		if self.reverse_z {
+			0.0
+		} else {
+			1.0
+		}
+	}
+}
+
+// right handed, y up, looking at -z, fovy in radians
+// maps near..far to depth 0..1, or 1..0 with reverse_z
+pub fn perspective(
+	fovy: f32,
+	aspect: f32,
+	near: f32,
+	far: f32,
+	reverse_z: bool,
+) -> M4 {
+	let f = 1.0 / (fovy / 2.0).tan();
+	let (a, b) = if reverse_z {
+		(near / (far - near), near * far / (far - near))
+	} else {
+		(far / (near - far), near * far / (near - far))
+	};
+	let mut result = M4::zeros();
+	result[(0, 0)] = f / aspect;
+	// vulkan clip space has y down
+	result[(1, 1)] = -f;
+	result[(2, 2)] = a;
+	result[(2, 3)] = b;
+	result[(3, 2)] = -1.0;
+	result
+}
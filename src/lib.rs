@@ -1,17 +1,27 @@
 pub mod bmtext;
 pub mod camcon;
+pub mod color;
+pub mod compman;
+pub mod depth;
 pub mod model;
+pub mod recorder;
 pub mod renderer;
+pub mod submit;
 pub mod teximg;
+pub mod view;
 
 mod base;
 mod camera;
 mod helper;
+mod materman;
+mod pool;
 mod rmod;
 mod shader;
 mod texman;
 mod vertex;
 
+pub use pool::AllocStats;
+
 pub type V2 = nalgebra::Vector2<f32>;
 pub type M4 = nalgebra::Matrix4<f32>;
 
@@ -0,0 +1,112 @@
+use bytemuck::{Pod, Zeroable};
+use std::collections::HashMap;
+use vulkano::shader::ShaderModule;
+
+use crate::helper::*;
+use crate::model::compiled_model::RenderFlags;
+use crate::model::modelman::Topology;
+use crate::pool::{AllocStats, UniformPool};
+use crate::rmod::{get_pipeline_tex, PipelineBase, PipelineKey};
+
+// uniform block of a material fragment shader, set = 2, binding = 0
+#[repr(C)]
+#[derive(Pod, Zeroable, Default, Debug, Clone, Copy)]
+pub struct MaterialParams {
+	pub data: [[f32; 4]; 4],
+}
+
+pub struct Material {
+	module: VkwShaderModule,
+	pub params: MaterialParams,
+	// built on first use, none = failed to build
+	pipelines: HashMap<RenderFlags, Option<VkwPipeline>>,
+}
+
+// like texman, material id is chosen by user
+#[derive(Default)]
+pub struct Materman {
+	materials: HashMap<i32, Material>,
+}
+
+impl Materman {
+	// safety: words must be valid spir-v, see Renderer::register_material_spirv
+	pub unsafe fn register_spirv(
+		&mut self,
+		device: VkwDevice,
+		id: i32,
+		words: &[u32],
+	) -> Result<(), String> {
+		let module = ShaderModule::from_words(device, words)
+			.map_err(|e| format!("{}", e))?;
+		if module.entry_point("main").is_none() {
+			return Err("no main entry point".to_string());
+		}
+		self.materials.insert(
+			id,
+			Material {
+				module,
+				params: Default::default(),
+				pipelines: HashMap::new(),
+			},
+		);
+		Ok(())
+	}
+
+	pub fn remove(&mut self, id: i32) {
+		self.materials.remove(&id);
+	}
+
+	pub fn set_params(&mut self, id: i32, params: MaterialParams) {
+		if let Some(material) = self.materials.get_mut(&id) {
+			material.params = params;
+		}
+	}
+
+	// called when the render pass or vertex shader changes
+	pub fn clear_pipelines(&mut self) {
+		for material in self.materials.values_mut() {
+			material.pipelines.clear();
+		}
+	}
+
+	pub fn get_pipeline(
+		&mut self,
+		id: i32,
+		flags: RenderFlags,
+		base: &PipelineBase,
+	) -> Option<VkwPipeline> {
+		let material = self.materials.get_mut(&id)?;
+		let module = &material.module;
+		material
+			.pipelines
+			.entry(flags)
+			.or_insert_with(|| {
+				let key = PipelineKey {
+					topology: Topology::Triangle,
+					flags,
+				};
+				match get_pipeline_tex(base, module.clone(), key) {
+					Ok(pipeline) => Some(pipeline),
+					Err(e) => {
+						eprintln!("ERROR: material {}: {}", id, e);
+						None
+					}
+				}
+			})
+			.clone()
+	}
+
+	// params are written to a pooled uniform buffer on every call
+	// pool layout must be set to set 2 of the pipeline layout
+	pub fn compile_set(
+		&self,
+		id: i32,
+		pool: &mut UniformPool<MaterialParams>,
+		memalloc: &VkwMemAlloc,
+		dstalloc: &VkwDstAlloc,
+		stats: &mut AllocStats,
+	) -> Option<VkwDescriptorSet> {
+		let material = self.materials.get(&id)?;
+		Some(pool.next(material.params, memalloc, dstalloc, stats))
+	}
+}
@@ -1,8 +1,14 @@
+use crate::M4;
+
 #[derive(Clone, Debug, Default)]
 pub struct Model {
 	pub vs: Vec<[f32; 4]>,
 	pub uvs: Vec<[f32; 2]>,
+	// per corner colors, see Face::cid
+	pub colors: Vec<[f32; 4]>,
 	pub faces: Vec<Face>,
+	pub lines: Vec<Line>,
+	pub points: Vec<Point>,
 }
 
 #[derive(Clone, Debug)]
@@ -11,4 +17,54 @@ pub struct Face {
 	pub vid: [usize; 3],
 	pub uvid: [usize; 3],
 	pub layer: i32,
+	// into colors, interpolated across the face; none = color everywhere
+	pub cid: Option<[usize; 3]>,
+}
+
+// untextured white, fill in the rest with ..Default::default()
+impl Default for Face {
+	fn default() -> Self {
+		Self {
+			color: [1.0; 4],
+			vid: [0; 3],
+			uvid: [0; 3],
+			layer: -1,
+			cid: None,
+		}
+	}
+}
+
+// untextured, 2 vids for a segment, more for a line strip
+#[derive(Clone, Debug)]
+pub struct Line {
+	pub color: [f32; 4],
+	pub vid: Vec<usize>,
+}
+
+// untextured
+#[derive(Clone, Debug)]
+pub struct Point {
+	pub color: [f32; 4],
+	pub vid: usize,
+}
+
+// one copy of a model, see Renderer::set_instances
+#[derive(Clone, Debug)]
+pub struct Instance {
+	// applied before the model transform
+	pub transform: M4,
+	// multiplies the shaded color
+	pub tint: [f32; 4],
+	// texture replacing the layer of textured faces
+	pub layer: Option<i32>,
+}
+
+impl Default for Instance {
+	fn default() -> Self {
+		Self {
+			transform: M4::identity(),
+			tint: [1.0; 4],
+			layer: None,
+		}
+	}
 }
@@ -1,7 +1,53 @@
-use crate::vertex::VertexTex;
+use crate::vertex::{InstanceData, VertexTex};
+use crate::M4;
+
+// fixed pipeline state of a model, pipelines are built per combination
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct RenderFlags {
+	pub depth_test: bool,
+	pub depth_write: bool,
+	// triangles only, counter clockwise is front
+	pub cull_back: bool,
+}
+
+impl Default for RenderFlags {
+	fn default() -> Self {
+		Self {
+			depth_test: true,
+			depth_write: true,
+			cull_back: false,
+		}
+	}
+}
 
 pub struct CompiledModel {
 	pub visible: bool,
 	pub z: i32,
+	pub material: Option<i32>,
+	pub mask: u32,
+	pub flags: RenderFlags,
+	// applied in vert.glsl, vertices stay in model space
+	pub transform: M4,
+	// multiplies the shaded color, alpha also by opacity
+	pub tint: [f32; 4],
+	pub opacity: f32,
+	// none = drawn once without instance data
+	pub instances: Option<Vec<InstanceData>>,
+	// bumped by ModelRef::borrow_mut, vertices may have changed
+	pub generation: u64,
+	// bumped when instances change
+	pub instance_generation: u64,
+	// unique triangle vertices
 	pub vertices: Vec<VertexTex>,
+	// triangle list into vertices
+	pub indices: Vec<u32>,
+	// line list, strips are expanded
+	pub lines: Vec<VertexTex>,
+	pub points: Vec<VertexTex>,
+}
+
+impl CompiledModel {
+	pub fn vertex_len(&self) -> usize {
+		self.vertices.len() + self.lines.len() + self.points.len()
+	}
 }
@@ -1,4 +1,6 @@
 pub mod cmodel;
-mod compiled_model;
+pub(crate) mod compiled_model;
+pub mod model_id;
 pub mod model_ref;
 pub(crate) mod modelman;
+mod range_alloc;
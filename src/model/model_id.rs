@@ -0,0 +1,8 @@
+// index into the model table and the generation of its user
+// stale ids of removed models are rejected, never reused:
+// an index whose generation would wrap is retired
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ModelId {
+	pub(crate) index: u32,
+	pub(crate) generation: u32,
+}
@@ -1,37 +1,158 @@
-use std::cell::{Ref, RefCell, RefMut};
+use std::cell::{Cell, Ref, RefCell, RefMut};
+use std::ops::{Deref, DerefMut};
 use std::rc::Rc;
 
 use super::compiled_model::CompiledModel;
+use super::model_id::ModelId;
+use crate::vertex::InstanceData;
+use crate::M4;
 
+// ids dropped by guards, removed by Modelman::gc
+pub(crate) type Removals = Rc<RefCell<Vec<ModelId>>>;
+
+// clones do not keep the model alive, see Renderer::remove_model
 #[derive(Clone)]
 pub struct ModelRef {
+	id: ModelId,
 	data: Rc<RefCell<CompiledModel>>,
+	// shared with renderer, set on every mutation
+	damage: Rc<Cell<bool>>,
+	// shared with modelman, set on every mutation
+	stale: Rc<Cell<bool>>,
+	removals: Removals,
 }
 
 impl ModelRef {
-	pub fn new(compiled_model: CompiledModel) -> Self {
+	pub(crate) fn new(
+		id: ModelId,
+		compiled_model: CompiledModel,
+		damage: Rc<Cell<bool>>,
+		stale: Rc<Cell<bool>>,
+		removals: Removals,
+	) -> Self {
 		Self {
+			id,
 			data: Rc::new(RefCell::new(compiled_model)),
+			damage,
+			stale,
+			removals,
 		}
 	}
 
+	pub fn id(&self) -> ModelId {
+		self.id
+	}
+
+	// remove the model when the guard is dropped
+	pub fn into_guard(self) -> ModelGuard {
+		ModelGuard(self)
+	}
+
 	pub fn set_z(&mut self, z: i32) {
-		self.data.borrow_mut().z = z;
+		self.borrow_state().z = z;
 	}
 
 	pub fn set_visibility(&mut self, visible: bool) {
-		self.data.borrow_mut().visible = visible;
+		self.borrow_state().visible = visible;
+	}
+
+	// none = builtin shader
+	pub fn set_material(&mut self, material: Option<i32>) {
+		self.borrow_state().material = material;
+	}
+
+	// drawn in views whose mask intersects this
+	pub fn set_view_mask(&mut self, mask: u32) {
+		self.borrow_state().mask = mask;
+	}
+
+	// without test, depth is written unconditionally
+	pub fn set_depth(&mut self, test: bool, write: bool) {
+		let mut data = self.borrow_state();
+		data.flags.depth_test = test;
+		data.flags.depth_write = write;
+	}
+
+	// for closed 3d meshes
+	pub fn set_cull_back(&mut self, cull_back: bool) {
+		self.borrow_state().flags.cull_back = cull_back;
+	}
+
+	// model to world, before the view camera
+	pub fn set_transform(&mut self, transform: M4) {
+		self.borrow_state().transform = transform;
+	}
+
+	// multiplies vertex color and texture, applied in the shader
+	pub fn set_tint(&mut self, tint: [f32; 4]) {
+		self.borrow_state().tint = tint;
+	}
+
+	// multiplies the tint alpha
+	pub fn set_opacity(&mut self, opacity: f32) {
+		self.borrow_state().opacity = opacity;
+	}
+
+	// back to a single draw without instance data
+	pub fn clear_instances(&mut self) {
+		self.set_instances(None);
 	}
 
-	pub fn dropped(&self) -> bool {
-		Rc::strong_count(&self.data) <= 1
+	// layers already mapped, see Renderer::set_instances
+	pub(crate) fn set_instances(
+		&mut self,
+		instances: Option<Vec<InstanceData>>,
+	) {
+		self.borrow_instances().instances = instances;
 	}
 
-	pub fn borrow(&self) -> Ref<CompiledModel> {
+	pub fn borrow(&self) -> Ref<'_, CompiledModel> {
 		self.data.borrow()
 	}
 
-	pub fn borrow_mut(&self) -> RefMut<CompiledModel> {
+	// vertices are not uploaded again
+	fn borrow_state(&self) -> RefMut<'_, CompiledModel> {
+		self.damage.set(true);
+		self.stale.set(true);
 		self.data.borrow_mut()
 	}
+
+	// vertices are not uploaded again, instances are
+	pub(crate) fn borrow_instances(&self) -> RefMut<'_, CompiledModel> {
+		let mut data = self.borrow_state();
+		data.instance_generation += 1;
+		data
+	}
+
+	// indices must stay below vertices.len(), else triangles are skipped
+	pub fn borrow_mut(&self) -> RefMut<'_, CompiledModel> {
+		let mut data = self.borrow_state();
+		data.generation += 1;
+		data
+	}
+}
+
+// opt-in scoped lifetime, removed on the next frame after drop
+pub struct ModelGuard(ModelRef);
+
+impl Deref for ModelGuard {
+	type Target = ModelRef;
+
+	fn deref(&self) -> &ModelRef {
+		&self.0
+	}
+}
+
+impl DerefMut for ModelGuard {
+	fn deref_mut(&mut self) -> &mut ModelRef {
+		&mut self.0
+	}
+}
+
+impl Drop for ModelGuard {
+	fn drop(&mut self) {
+		self.0.removals.borrow_mut().push(self.0.id);
+		self.0.damage.set(true);
+		self.0.stale.set(true);
+	}
 }
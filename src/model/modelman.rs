@@ -1,21 +1,100 @@
-use std::cell::Ref;
+use std::cell::{Cell, Ref};
 use std::collections::HashMap;
+use std::rc::Rc;
 use std::sync::Arc;
-use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer};
+use vulkano::buffer::{
+	BufferContents, BufferUsage, CpuAccessibleBuffer, TypedBufferAccess,
+};
 
-use super::cmodel::{Face, Model};
-use super::compiled_model::CompiledModel;
-use super::model_ref::ModelRef;
+use super::cmodel::{Face, Line, Model, Point};
+use super::compiled_model::{CompiledModel, RenderFlags};
+use super::model_id::ModelId;
+use super::model_ref::{ModelRef, Removals};
+use super::range_alloc::RangeAlloc;
 use crate::helper::*;
-use crate::vertex::VertexTex;
+use crate::pool::{AllocStats, GrowBuffer};
+use crate::vertex::{InstanceData, VertexTex};
+use crate::M4;
 
-const BUFSIZE: usize = 1 << 24;
-type VertexTexBuffer = Arc<CpuAccessibleBuffer<[VertexTex; BUFSIZE]>>;
+// in elements, capacity grows and shrinks by powers of two
+const MIN_CAPACITY: usize = 1 << 12;
+pub type VertexTexBuffer = Arc<CpuAccessibleBuffer<[VertexTex]>>;
+pub type IndexBuffer = Arc<CpuAccessibleBuffer<[u32]>>;
+pub type InstanceBuffer = Arc<CpuAccessibleBuffer<[InstanceData]>>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Topology {
+	Triangle = 0,
+	Line = 1,
+	Point = 2,
+}
+
+// a run of contiguous vertices sharing topology, material, mask, flags,
+// transform, tint and instances
+// triangles are indexed, first and count are in the index buffer
+#[derive(Clone, Debug)]
+pub struct DrawCall {
+	pub topology: Topology,
+	pub material: Option<i32>,
+	pub mask: u32,
+	pub flags: RenderFlags,
+	pub transform: M4,
+	// with opacity applied
+	pub tint: [f32; 4],
+	pub first: u32,
+	pub count: u32,
+	// 0, 1 for models without instances
+	pub first_instance: u32,
+	pub instance_count: u32,
+}
+
+impl DrawCall {
+	// next contiguous vertices with the same state
+	fn continued_by(&self, other: &DrawCall) -> bool {
+		self.first + self.count == other.first
+			&& self.topology == other.topology
+			&& self.material == other.material
+			&& self.mask == other.mask
+			&& self.flags == other.flags
+			&& self.transform == other.transform
+			&& self.tint == other.tint
+			&& self.first_instance == other.first_instance
+			&& self.instance_count == other.instance_count
+	}
+}
 
 pub struct Modelman {
+	// replaced when resized, must be rebound
 	pub buffer: VertexTexBuffer,
+	// same, holds indices into buffer, not into the model
+	pub index_buffer: IndexBuffer,
+	// same, element 0 is the default instance
+	pub instance_buffer: InstanceBuffer,
+	memalloc: VkwMemAlloc,
+	alloc: RangeAlloc,
+	index_alloc: RangeAlloc,
+	instance_alloc: RangeAlloc,
+	// in z order
+	pub draws: Vec<DrawCall>,
+	// triangle draw of each model, not merged, for wireframe
+	pub ranges: Vec<DrawCall>,
 	cached_size: Option<usize>, // none = dirty
-	models: Vec<ModelRef>,
+	// edges of ranges and their rebased draws, cleared with cached_size
+	edges: Option<(VertexTexBuffer, Vec<DrawCall>)>,
+	edge_buffer: GrowBuffer<VertexTex>,
+	// in insertion order, the draw order among equal z
+	models: Vec<ModelSlot>,
+	// by id index: current generation, position in models if live
+	ids: Vec<(u32, Option<usize>)>,
+	free_ids: Vec<u32>,
+	// models has slots of removed models, dropped in gc
+	released: bool,
+	// redraw request, shared with renderer and model refs
+	damage: Rc<Cell<bool>>,
+	// set by model refs, resets cached_size
+	stale: Rc<Cell<bool>>,
+	// shared with model refs, for guards
+	removals: Removals,
 }
 
 fn build_face(
@@ -47,9 +126,16 @@ fn build_face(
 			Some(x) => *x,
 			None => return None,
 		};
+		let color = match face.cid {
+			Some(cid) => match model.colors.get(cid[idx]) {
+				Some(x) => *x,
+				None => return None,
+			},
+			None => face.color,
+		};
 		vs[idx] = VertexTex {
 			pos,
-			color: face.color,
+			color,
 			tex_coord,
 			tex_layer,
 		};
@@ -57,23 +143,217 @@ fn build_face(
 	Some(vs)
 }
 
+// a model keeps its range in the buffer until removed or repacked
+struct ModelSlot {
+	model: ModelRef,
+	// vertices, then lines, then points; none = not allocated
+	offset: Option<u32>,
+	len: u32,
+	// allocated length, at least len
+	reserved: u32,
+	index_offset: Option<u32>,
+	index_len: u32,
+	index_reserved: u32,
+	instance_offset: Option<u32>,
+	instance_len: u32,
+	// of the vertices in the buffer
+	generation: u64,
+	instance_generation: u64,
+	// not written to buffer yet
+	dirty: bool,
+	instances_dirty: bool,
+}
+
+fn visible_sorted(
+	models: &[ModelSlot],
+) -> Vec<(&ModelSlot, Ref<'_, CompiledModel>)> {
+	let mut buffers: Vec<_> = models
+		.iter()
+		.map(|x| (x, x.model.borrow()))
+		.filter(|(_, x)| x.visible)
+		.collect();
+	buffers.sort_by_key(|(_, x)| x.z);
+	buffers
+}
+
+fn untextured(pos: [f32; 4], color: [f32; 4]) -> VertexTex {
+	VertexTex {
+		pos,
+		color,
+		tex_coord: [0.0; 2],
+		tex_layer: -1,
+	}
+}
+
+fn build_line(model: &Model, line: &Line) -> Option<Vec<VertexTex>> {
+	if line.vid.len() < 2 {
+		return None;
+	}
+	let mut vs = Vec::new();
+	for pair in line.vid.windows(2) {
+		for vid in pair {
+			let pos = *model.vs.get(*vid)?;
+			vs.push(untextured(pos, line.color));
+		}
+	}
+	Some(vs)
+}
+
+fn build_point(model: &Model, point: &Point) -> Option<VertexTex> {
+	let pos = *model.vs.get(point.vid)?;
+	Some(untextured(pos, point.color))
+}
+
+fn new_buffer<T>(
+	memalloc: &VkwMemAlloc,
+	capacity: usize,
+	usage: BufferUsage,
+) -> Arc<CpuAccessibleBuffer<[T]>>
+where
+	[T]: BufferContents,
+{
+	unsafe {
+		CpuAccessibleBuffer::uninitialized_array(
+			memalloc,
+			capacity as u64,
+			usage,
+			true,
+		)
+		.unwrap()
+	}
+}
+
+fn new_vertex_buffer(
+	memalloc: &VkwMemAlloc,
+	capacity: usize,
+) -> VertexTexBuffer {
+	let usage = BufferUsage {
+		vertex_buffer: true,
+		// for compute passes
+		storage_buffer: true,
+		..BufferUsage::empty()
+	};
+	new_buffer(memalloc, capacity, usage)
+}
+
+fn new_index_buffer(memalloc: &VkwMemAlloc, capacity: usize) -> IndexBuffer {
+	let usage = BufferUsage {
+		index_buffer: true,
+		..BufferUsage::empty()
+	};
+	new_buffer(memalloc, capacity, usage)
+}
+
+fn new_instance_buffer(
+	memalloc: &VkwMemAlloc,
+	capacity: usize,
+) -> InstanceBuffer {
+	let usage = BufferUsage {
+		vertex_buffer: true,
+		..BufferUsage::empty()
+	};
+	new_buffer(memalloc, capacity, usage)
+}
+
+// with the default instance reserved
+fn new_instance_alloc(capacity: u32) -> RangeAlloc {
+	let mut alloc = RangeAlloc::new(capacity);
+	alloc.alloc(1);
+	alloc
+}
+
+// merge corners with identical content, shared by neighbouring faces
+fn build_indexed(faces: Vec<VertexTex>) -> (Vec<VertexTex>, Vec<u32>) {
+	let mut vertices = Vec::new();
+	let mut indices = Vec::with_capacity(faces.len());
+	let mut seen: HashMap<[u32; 11], u32> = HashMap::new();
+	for v in faces {
+		let idx = *seen.entry(bytemuck::cast(v)).or_insert_with(|| {
+			vertices.push(v);
+			vertices.len() as u32 - 1
+		});
+		indices.push(idx);
+	}
+	(vertices, indices)
+}
+
+// replaces vertices, indices, lines and points of compiled
+fn build_geometry(
+	model: &Model,
+	mapper: &HashMap<i32, i32>,
+	compiled: &mut CompiledModel,
+) {
+	let mut invalid = 0;
+	let mut corners = Vec::new();
+	for face in model.faces.iter() {
+		match build_face(model, face, mapper) {
+			Some(vs) => corners.extend(vs),
+			None => invalid += 1,
+		}
+	}
+	let (vertices, indices) = build_indexed(corners);
+	let mut lines = Vec::new();
+	for line in model.lines.iter() {
+		match build_line(model, line) {
+			Some(vs) => lines.extend(vs),
+			None => invalid += 1,
+		}
+	}
+	let mut points = Vec::new();
+	for point in model.points.iter() {
+		match build_point(model, point) {
+			Some(v) => points.push(v),
+			None => invalid += 1,
+		}
+	}
+	if invalid > 0 {
+		eprintln!("ERROR: Skipped {} invalid primitives", invalid);
+	}
+	compiled.vertices = vertices;
+	compiled.indices = indices;
+	compiled.lines = lines;
+	compiled.points = points;
+}
+
+// grow to fit, shrink when a quarter is used, keep half free
+fn resized_capacity(capacity: usize, len: usize) -> Option<usize> {
+	if len > capacity {
+		Some(len.next_power_of_two())
+	} else if len < capacity / 4 && capacity > MIN_CAPACITY {
+		Some((len * 2).next_power_of_two().max(MIN_CAPACITY))
+	} else {
+		None
+	}
+}
+
 impl Modelman {
-	pub fn new(memalloc: VkwMemAlloc) -> Self {
-		let buffer = unsafe {
-			CpuAccessibleBuffer::uninitialized(
-				&memalloc,
+	pub fn new(memalloc: VkwMemAlloc, damage: Rc<Cell<bool>>) -> Self {
+		Self {
+			buffer: new_vertex_buffer(&memalloc, MIN_CAPACITY),
+			index_buffer: new_index_buffer(&memalloc, MIN_CAPACITY),
+			instance_buffer: new_instance_buffer(&memalloc, MIN_CAPACITY),
+			memalloc,
+			alloc: RangeAlloc::new(MIN_CAPACITY as u32),
+			index_alloc: RangeAlloc::new(MIN_CAPACITY as u32),
+			instance_alloc: new_instance_alloc(MIN_CAPACITY as u32),
+			draws: Vec::new(),
+			ranges: Vec::new(),
+			cached_size: None,
+			edges: None,
+			edge_buffer: GrowBuffer::new(
 				BufferUsage {
 					vertex_buffer: true,
 					..BufferUsage::empty()
 				},
-				true,
-			)
-			.unwrap()
-		};
-		Self {
-			buffer,
-			cached_size: None,
+				false,
+			),
 			models: Default::default(),
+			ids: Vec::new(),
+			free_ids: Vec::new(),
+			released: false,
+			damage,
+			stale: Default::default(),
+			removals: Default::default(),
 		}
 	}
 
@@ -82,78 +362,482 @@ impl Modelman {
 		model: &Model,
 		mapper: &HashMap<i32, i32>,
 	) -> ModelRef {
-		let mut invalid = 0;
-		let mut vertices = Vec::new();
-		for face in model.faces.iter() {
-			match build_face(model, face, mapper) {
-				Some(vs) => vertices.extend(vs),
-				None => invalid += 1,
-			}
-		}
-		if invalid > 0 {
-			eprintln!("ERROR: Skipped {} invalid faces", invalid);
-		}
-		let model = CompiledModel {
+		let mut compiled = CompiledModel {
 			visible: true,
 			z: 0,
-			vertices,
+			material: None,
+			mask: !0,
+			flags: Default::default(),
+			transform: M4::identity(),
+			tint: [1.0; 4],
+			opacity: 1.0,
+			instances: None,
+			generation: 0,
+			instance_generation: 0,
+			vertices: Vec::new(),
+			indices: Vec::new(),
+			lines: Vec::new(),
+			points: Vec::new(),
+		};
+		build_geometry(model, mapper, &mut compiled);
+		let len = compiled.vertex_len() as u32;
+		let index_len = compiled.indices.len() as u32;
+		let index = match self.free_ids.pop() {
+			Some(x) => x,
+			None => {
+				self.ids.push((0, None));
+				self.ids.len() as u32 - 1
+			}
 		};
-		let model = ModelRef::new(model);
-		self.models.push(model.clone());
+		let entry = &mut self.ids[index as usize];
+		entry.1 = Some(self.models.len());
+		let id = ModelId {
+			index,
+			generation: entry.0,
+		};
+		let model = ModelRef::new(
+			id,
+			compiled,
+			self.damage.clone(),
+			self.stale.clone(),
+			self.removals.clone(),
+		);
+		self.models.push(ModelSlot {
+			model: model.clone(),
+			offset: None,
+			len,
+			reserved: 0,
+			index_offset: None,
+			index_len,
+			index_reserved: 0,
+			instance_offset: None,
+			instance_len: 0,
+			generation: 0,
+			instance_generation: 0,
+			dirty: true,
+			instances_dirty: false,
+		});
 		self.cached_size = None;
+		self.damage.set(true);
 		model
 	}
 
+	// replace geometry only, the slot and model state are kept
+	// the ranges are reused in refresh if the new geometry fits
+	pub fn update(
+		&mut self,
+		model_ref: &ModelRef,
+		model: &Model,
+		mapper: &HashMap<i32, i32>,
+	) {
+		build_geometry(model, mapper, &mut model_ref.borrow_mut());
+		self.cached_size = None;
+	}
+
+	// layers of removed textures become -1, untextured
 	pub fn map_tex(&mut self, mapper: HashMap<i32, i32>) {
-		for model in self.models.iter_mut() {
-			let mut model = model.borrow_mut();
+		let map = |l: &mut i32| {
+			if *l >= 0 {
+				*l = mapper.get(l).copied().unwrap_or(-1);
+			}
+		};
+		for slot in self.models.iter_mut() {
+			let mut model = slot.model.borrow_mut();
 			for v in model.vertices.iter_mut() {
-				let l = &mut v.tex_layer;
-				if *l >= 0 {
-					*l = *mapper.get(l).unwrap();
-				}
+				map(&mut v.tex_layer);
+			}
+			let instanced = model.instances.is_some();
+			drop(model);
+			if !instanced {
+				continue;
+			}
+			let mut model = slot.model.borrow_instances();
+			for instance in model.instances.iter_mut().flatten() {
+				map(&mut instance.tex_layer);
 			}
 		}
 		self.cached_size = None;
 	}
 
+	fn position(&self, id: ModelId) -> Option<usize> {
+		match self.ids.get(id.index as usize) {
+			Some((generation, position)) if *generation == id.generation => {
+				*position
+			}
+			_ => None,
+		}
+	}
+
+	pub fn get(&self, id: ModelId) -> Option<&ModelRef> {
+		let position = self.position(id)?;
+		Some(&self.models[position].model)
+	}
+
+	// in insertion order
+	pub fn iter(&self) -> impl Iterator<Item = &ModelRef> {
+		self.models
+			.iter()
+			.map(|x| &x.model)
+			.filter(|x| self.position(x.id()).is_some())
+	}
+
+	// false if already removed, the slot is dropped in the next gc
+	pub fn remove(&mut self, id: ModelId) -> bool {
+		self.release(id)
+	}
+
+	// remove models of dropped guards and compact once for all removals
 	pub fn gc(&mut self) {
-		for model in std::mem::take(&mut self.models).into_iter() {
-			if !model.dropped() {
-				self.models.push(model);
-			} else {
-				self.cached_size = None;
+		let removals = std::mem::take(&mut *self.removals.borrow_mut());
+		for id in removals {
+			self.release(id);
+		}
+		if std::mem::take(&mut self.released) {
+			self.compact();
+		}
+	}
+
+	// frees the ranges and the id, the slot is dropped in compact
+	fn release(&mut self, id: ModelId) -> bool {
+		let position = match self.position(id) {
+			Some(x) => x,
+			None => return false,
+		};
+		let slot = &self.models[position];
+		if let Some(offset) = slot.offset {
+			self.alloc.free(offset, slot.reserved);
+		}
+		if let Some(offset) = slot.index_offset {
+			self.index_alloc.free(offset, slot.index_reserved);
+		}
+		if let Some(offset) = slot.instance_offset {
+			self.instance_alloc.free(offset, slot.instance_len);
+		}
+		let entry = &mut self.ids[id.index as usize];
+		entry.1 = None;
+		// retire the index instead of wrapping its generation
+		if entry.0 < u32::MAX {
+			entry.0 += 1;
+			self.free_ids.push(id.index);
+		}
+		self.released = true;
+		self.cached_size = None;
+		self.damage.set(true);
+		true
+	}
+
+	// drop released slots and renumber the rest in one pass
+	// a released index may already belong to a newer generation
+	fn compact(&mut self) {
+		let ids = &mut self.ids;
+		self.models.retain(|slot| {
+			let id = slot.model.id();
+			let (generation, position) = ids[id.index as usize];
+			generation == id.generation && position.is_some()
+		});
+		for (position, slot) in self.models.iter().enumerate() {
+			ids[slot.model.id().index as usize].1 = Some(position);
+		}
+	}
+
+	// triangle edges as line list, for devices without polygon mode line
+	// rebuilt only after write_buffer repacked or invalidate_edges
+	pub fn edges(
+		&mut self,
+		stats: &mut AllocStats,
+	) -> Option<(VertexTexBuffer, Vec<DrawCall>)> {
+		if self.edges.is_none() {
+			let (edges, ranges) = self.build_edges();
+			if edges.is_empty() {
+				return None;
+			}
+			let buffer = self.edge_buffer.write(&edges, &self.memalloc, stats);
+			self.edges = Some((buffer, ranges));
+		}
+		self.edges.clone()
+	}
+
+	// vertices were changed on gpu, e.g. by compute passes
+	pub fn invalidate_edges(&mut self) {
+		self.edges = None;
+	}
+
+	// ranges are rebased into the returned edges, valid after write_buffer
+	fn build_edges(&self) -> (Vec<VertexTex>, Vec<DrawCall>) {
+		let mut edges = Vec::new();
+		let mut ranges = Vec::new();
+		let (vertices, indices) =
+			match (self.buffer.read(), self.index_buffer.read()) {
+				(Ok(x), Ok(y)) => (x, y),
+				_ => return (edges, ranges),
+			};
+		for draw in self.ranges.iter() {
+			let first = edges.len();
+			let end = (draw.first + draw.count) as usize;
+			for tri in indices[draw.first as usize..end].chunks_exact(3) {
+				for (a, b) in [(0, 1), (1, 2), (2, 0)] {
+					edges.push(vertices[tri[a] as usize]);
+					edges.push(vertices[tri[b] as usize]);
+				}
 			}
+			ranges.push(DrawCall {
+				first: first as u32,
+				count: (edges.len() - first) as u32,
+				..draw.clone()
+			});
 		}
+		(edges, ranges)
 	}
 
-	pub fn write_buffer(&mut self) -> Option<usize> {
+	// end of used part of the buffer, may contain unused holes
+	// valid after write_buffer
+	pub fn vertex_count(&self) -> usize {
+		self.cached_size.unwrap_or(0)
+	}
+
+	// pick up vertex changes made through ModelRef::borrow_mut
+	// and instance changes
+	fn refresh(&mut self) {
+		for slot in self.models.iter_mut() {
+			let model = slot.model.borrow();
+			if model.instance_generation != slot.instance_generation {
+				slot.instance_generation = model.instance_generation;
+				slot.instances_dirty = true;
+				let len = model.instances.as_ref().map_or(0, |x| x.len());
+				if len as u32 != slot.instance_len {
+					if let Some(offset) = slot.instance_offset.take() {
+						self.instance_alloc.free(offset, slot.instance_len);
+					}
+					slot.instance_len = len as u32;
+				}
+			}
+			if model.generation == slot.generation {
+				continue;
+			}
+			slot.generation = model.generation;
+			slot.dirty = true;
+			// keep the range when the new length fits
+			slot.len = model.vertex_len() as u32;
+			if slot.len > slot.reserved {
+				if let Some(offset) = slot.offset.take() {
+					self.alloc.free(offset, slot.reserved);
+				}
+			}
+			slot.index_len = model.indices.len() as u32;
+			if slot.index_len > slot.index_reserved {
+				if let Some(offset) = slot.index_offset.take() {
+					self.index_alloc.free(offset, slot.index_reserved);
+				}
+			}
+		}
+	}
+
+	// give new models ranges, repack all buffers when one is full
+	// or mostly empty
+	fn allocate(&mut self, stats: &mut AllocStats) {
+		let mut failed = false;
+		for slot in self.models.iter_mut() {
+			if slot.offset.is_none() {
+				slot.offset = self.alloc.alloc(slot.len);
+				slot.reserved = slot.len;
+				failed |= slot.offset.is_none();
+				// indices hold the vertex offset
+				slot.dirty = true;
+			}
+			if slot.index_offset.is_none() {
+				slot.index_offset = self.index_alloc.alloc(slot.index_len);
+				slot.index_reserved = slot.index_len;
+				failed |= slot.index_offset.is_none();
+			}
+			if slot.instance_offset.is_none() {
+				let len = slot.instance_len;
+				slot.instance_offset = self.instance_alloc.alloc(len);
+				failed |= slot.instance_offset.is_none();
+				slot.instances_dirty = true;
+			}
+		}
+		let total: usize = self.models.iter().map(|x| x.len as usize).sum();
+		let capacity = self.buffer.len() as usize;
+		let resized = resized_capacity(capacity, total);
+		let index_total: usize =
+			self.models.iter().map(|x| x.index_len as usize).sum();
+		let index_capacity = self.index_buffer.len() as usize;
+		let index_resized = resized_capacity(index_capacity, index_total);
+		let instance_total: usize =
+			self.models.iter().map(|x| x.instance_len as usize).sum();
+		let instance_capacity = self.instance_buffer.len() as usize;
+		let instance_resized =
+			resized_capacity(instance_capacity, instance_total + 1);
+		if !failed
+			&& resized.is_none()
+			&& index_resized.is_none()
+			&& instance_resized.is_none()
+		{
+			return;
+		}
+		if let Some(capacity) = resized {
+			self.buffer = new_vertex_buffer(&self.memalloc, capacity);
+			stats.buffers += 1;
+		}
+		if let Some(capacity) = index_resized {
+			self.index_buffer = new_index_buffer(&self.memalloc, capacity);
+			stats.buffers += 1;
+		}
+		if let Some(capacity) = instance_resized {
+			self.instance_buffer =
+				new_instance_buffer(&self.memalloc, capacity);
+			stats.buffers += 1;
+		}
+		self.alloc = RangeAlloc::new(self.buffer.len() as u32);
+		self.index_alloc = RangeAlloc::new(self.index_buffer.len() as u32);
+		self.instance_alloc =
+			new_instance_alloc(self.instance_buffer.len() as u32);
+		for slot in self.models.iter_mut() {
+			slot.offset = self.alloc.alloc(slot.len);
+			slot.reserved = slot.len;
+			slot.index_offset = self.index_alloc.alloc(slot.index_len);
+			slot.index_reserved = slot.index_len;
+			let len = slot.instance_len;
+			slot.instance_offset = self.instance_alloc.alloc(len);
+			slot.dirty = true;
+			slot.instances_dirty = true;
+		}
+	}
+
+	// write dirty models only
+	fn upload(&mut self) -> bool {
+		let buffer = self.buffer.clone();
+		let index_buffer = self.index_buffer.clone();
+		let instance_buffer = self.instance_buffer.clone();
+		let (mut writer, mut index_writer, mut instance_writer) = match (
+			buffer.write(),
+			index_buffer.write(),
+			instance_buffer.write(),
+		) {
+			(Ok(x), Ok(y), Ok(z)) => (x, y, z),
+			_ => {
+				eprintln!("ERROR: Gpu locked");
+				return false;
+			}
+		};
+		instance_writer[0] = Default::default();
+		for slot in self.models.iter_mut().filter(|x| x.instances_dirty) {
+			let model = slot.model.borrow();
+			let first = slot.instance_offset.unwrap() as usize;
+			let range =
+				&mut instance_writer[first..first + slot.instance_len as usize];
+			for (i, j) in range.iter_mut().zip(model.instances.iter().flatten())
+			{
+				*i = *j;
+			}
+			slot.instances_dirty = false;
+		}
+		for slot in self.models.iter_mut().filter(|x| x.dirty) {
+			let model = slot.model.borrow();
+			let first = slot.offset.unwrap() as usize;
+			let range = &mut writer[first..first + slot.len as usize];
+			let vertices = model
+				.vertices
+				.iter()
+				.chain(&model.lines)
+				.chain(&model.points);
+			for (v, w) in range.iter_mut().zip(vertices) {
+				*v = *w;
+			}
+			// borrow_mut can shrink vertices without touching indices
+			let vertex_len = model.vertices.len() as u32;
+			if model.indices.iter().any(|&i| i >= vertex_len) {
+				eprintln!(
+					"ERROR: {:?} has indices out of range, triangles skipped",
+					slot.model.id()
+				);
+				slot.index_len = 0;
+			}
+			// global indices, so neighbouring models can share a draw
+			let index_first = slot.index_offset.unwrap() as usize;
+			let range = &mut index_writer
+				[index_first..index_first + slot.index_len as usize];
+			for (i, j) in range.iter_mut().zip(model.indices.iter()) {
+				*i = *j + first as u32;
+			}
+			slot.dirty = false;
+		}
+		true
+	}
+
+	fn build_draws(&mut self) {
+		self.draws.clear();
+		self.ranges.clear();
+		for (slot, model) in visible_sorted(&self.models).iter() {
+			let (first_instance, instance_count) = match &model.instances {
+				Some(x) => (slot.instance_offset.unwrap(), x.len() as u32),
+				None => (0, 1),
+			};
+			if instance_count == 0 {
+				continue;
+			}
+			let lines = slot.offset.unwrap() + model.vertices.len() as u32;
+			let points = lines + model.lines.len() as u32;
+			let parts = [
+				(
+					Topology::Triangle,
+					model.material,
+					slot.index_offset.unwrap(),
+					slot.index_len,
+				),
+				(Topology::Line, None, lines, model.lines.len() as u32),
+				(Topology::Point, None, points, model.points.len() as u32),
+			];
+			for (topology, material, first, count) in parts {
+				if count == 0 {
+					continue;
+				}
+				let mask = model.mask;
+				let flags = model.flags;
+				let transform = model.transform;
+				let [r, g, b, a] = model.tint;
+				let tint = [r, g, b, a * model.opacity];
+				let call = DrawCall {
+					topology,
+					material,
+					mask,
+					flags,
+					transform,
+					tint,
+					first,
+					count,
+					first_instance,
+					instance_count,
+				};
+				if topology == Topology::Triangle {
+					self.ranges.push(call.clone());
+				}
+				match self.draws.last_mut() {
+					Some(draw) if draw.continued_by(&call) => {
+						draw.count += count;
+					}
+					_ => self.draws.push(call),
+				}
+			}
+		}
+	}
+
+	pub fn write_buffer(&mut self, stats: &mut AllocStats) -> Option<usize> {
 		self.gc();
+		if self.stale.replace(false) {
+			self.cached_size = None;
+		}
 		if self.cached_size.is_some() {
 			return self.cached_size;
 		}
-		let mut buffers: Vec<Ref<CompiledModel>> = self
-			.models
-			.iter()
-			.map(|x| x.borrow())
-			.filter(|x| x.visible)
-			.collect();
-		buffers.sort_by_key(|x| x.z);
-		let len = buffers.iter().map(|x| x.vertices.len()).sum();
-
-		let buffer = self.buffer.clone();
-		let mut writer = if let Ok(writer) = buffer.write() {
-			writer
-		} else {
-			eprintln!("ERROR: Gpu locked");
+		self.refresh();
+		self.allocate(stats);
+		if !self.upload() {
 			return None;
-		};
-		for (v, w) in writer
-			.iter_mut()
-			.zip(buffers.iter().flat_map(|x| &x.vertices))
-		{
-			*v = *w;
 		}
+		self.build_draws();
+		self.edges = None;
+		let len = self.alloc.high_water() as usize;
 		self.cached_size = Some(len);
 		Some(len)
 	}
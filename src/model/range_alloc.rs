@@ -0,0 +1,58 @@
+// first fit allocator of vertex ranges in the vertex buffer
+pub struct RangeAlloc {
+	// [start, len], sorted by start, never adjacent
+	free: Vec<[u32; 2]>,
+	capacity: u32,
+}
+
+impl RangeAlloc {
+	pub fn new(capacity: u32) -> Self {
+		let free = if capacity > 0 {
+			vec![[0, capacity]]
+		} else {
+			Vec::new()
+		};
+		Self { free, capacity }
+	}
+
+	pub fn alloc(&mut self, len: u32) -> Option<u32> {
+		if len == 0 {
+			return Some(0);
+		}
+		let idx = self.free.iter().position(|x| x[1] >= len)?;
+		let [start, free_len] = self.free[idx];
+		if free_len == len {
+			self.free.remove(idx);
+		} else {
+			self.free[idx] = [start + len, free_len - len];
+		}
+		Some(start)
+	}
+
+	pub fn free(&mut self, start: u32, len: u32) {
+		if len == 0 {
+			return;
+		}
+		let mut idx = self.free.partition_point(|x| x[0] < start);
+		self.free.insert(idx, [start, len]);
+		if idx > 0 && self.free[idx - 1][0] + self.free[idx - 1][1] == start {
+			self.free[idx - 1][1] += len;
+			self.free.remove(idx);
+			idx -= 1;
+		}
+		if idx + 1 < self.free.len()
+			&& self.free[idx][0] + self.free[idx][1] == self.free[idx + 1][0]
+		{
+			self.free[idx][1] += self.free[idx + 1][1];
+			self.free.remove(idx + 1);
+		}
+	}
+
+	// end of the last allocated range
+	pub fn high_water(&self) -> u32 {
+		match self.free.last() {
+			Some([start, len]) if start + len == self.capacity => *start,
+			_ => self.capacity,
+		}
+	}
+}
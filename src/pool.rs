@@ -0,0 +1,156 @@
+use std::sync::Arc;
+use vulkano::buffer::{BufferContents, BufferUsage, CpuAccessibleBuffer};
+use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
+
+use crate::helper::*;
+
+// cumulative counts, compare two frames to check steady state
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AllocStats {
+	pub buffers: u64,
+	pub descriptor_sets: u64,
+	pub samplers: u64,
+}
+
+type Slot<T> = (Arc<CpuAccessibleBuffer<T>>, VkwDescriptorSet);
+
+// uniform buffers at binding 0 of one set layout, each with its set,
+// handed out in order and rewound every frame
+pub(crate) struct UniformPool<T: BufferContents> {
+	layout: Option<VkwTexLayout>,
+	slots: Vec<Slot<T>>,
+	cursor: usize,
+}
+
+impl<T: BufferContents> Default for UniformPool<T> {
+	fn default() -> Self {
+		Self {
+			layout: None,
+			slots: Vec::new(),
+			cursor: 0,
+		}
+	}
+}
+
+impl<T: BufferContents> UniformPool<T> {
+	// slots are only valid for the layout they were built with
+	pub fn set_layout(&mut self, layout: VkwTexLayout) {
+		if let Some(old) = self.layout.as_ref() {
+			if Arc::ptr_eq(old, &layout) {
+				return;
+			}
+		}
+		self.slots.clear();
+		self.cursor = 0;
+		self.layout = Some(layout);
+	}
+
+	// the frame using the slots must have finished
+	pub fn rewind(&mut self) {
+		self.cursor = 0;
+	}
+
+	pub fn next(
+		&mut self,
+		data: T,
+		memalloc: &VkwMemAlloc,
+		dstalloc: &VkwDstAlloc,
+		stats: &mut AllocStats,
+	) -> VkwDescriptorSet {
+		while self.cursor < self.slots.len() {
+			let (buffer, set) = &self.slots[self.cursor];
+			self.cursor += 1;
+			// still locked by gpu, try next slot
+			if let Ok(mut lock) = buffer.write() {
+				*lock = data;
+				return set.clone();
+			}
+		}
+		let buffer = CpuAccessibleBuffer::from_data(
+			memalloc,
+			BufferUsage {
+				uniform_buffer: true,
+				..BufferUsage::empty()
+			},
+			false,
+			data,
+		)
+		.unwrap();
+		let set = PersistentDescriptorSet::new(
+			dstalloc,
+			self.layout.clone().unwrap(),
+			[WriteDescriptorSet::buffer(0, buffer.clone())],
+		)
+		.unwrap();
+		stats.buffers += 1;
+		stats.descriptor_sets += 1;
+		self.slots.push((buffer, set.clone()));
+		self.cursor += 1;
+		set
+	}
+}
+
+// one host visible array reused across frames, reallocated with room to
+// grow when too small or still locked by gpu
+pub(crate) struct GrowBuffer<T>
+where
+	[T]: BufferContents,
+{
+	usage: BufferUsage,
+	host_cached: bool,
+	buffer: Option<Arc<CpuAccessibleBuffer<[T]>>>,
+}
+
+impl<T> GrowBuffer<T>
+where
+	[T]: BufferContents,
+	T: Copy,
+{
+	pub fn new(usage: BufferUsage, host_cached: bool) -> Self {
+		Self {
+			usage,
+			host_cached,
+			buffer: None,
+		}
+	}
+
+	// contents are undefined, the buffer may be longer than len
+	pub fn reserve(
+		&mut self,
+		len: usize,
+		memalloc: &VkwMemAlloc,
+		stats: &mut AllocStats,
+	) -> Arc<CpuAccessibleBuffer<[T]>> {
+		if let Some(buffer) = self.buffer.as_ref() {
+			if let Ok(lock) = buffer.write() {
+				if lock.len() >= len {
+					return buffer.clone();
+				}
+			}
+		}
+		let buffer = unsafe {
+			CpuAccessibleBuffer::uninitialized_array(
+				memalloc,
+				len.max(1).next_power_of_two() as u64,
+				self.usage,
+				self.host_cached,
+			)
+			.unwrap()
+		};
+		stats.buffers += 1;
+		self.buffer = Some(buffer.clone());
+		buffer
+	}
+
+	// data is written to the front
+	pub fn write(
+		&mut self,
+		data: &[T],
+		memalloc: &VkwMemAlloc,
+		stats: &mut AllocStats,
+	) -> Arc<CpuAccessibleBuffer<[T]>> {
+		let buffer = self.reserve(data.len(), memalloc, stats);
+		buffer.write().unwrap()[..data.len()].copy_from_slice(data);
+		buffer
+	}
+}
@@ -0,0 +1,177 @@
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{Delay, Frame};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer};
+use vulkano::command_buffer::CopyImageToBufferInfo;
+use vulkano::format::Format;
+use vulkano::image::ImageAccess;
+
+use crate::helper::*;
+use crate::pool::{AllocStats, GrowBuffer};
+use crate::teximg::{TexImage, Teximg};
+
+pub enum RecordFormat {
+	// numbered png files in a directory
+	Png { dir: String },
+	Gif { path: String, delay_ms: u32 },
+}
+
+type CaptureBuffer = Arc<CpuAccessibleBuffer<[u8]>>;
+
+// frames waiting for the encoder, later captures are dropped
+const QUEUE_LEN: usize = 8;
+
+pub struct Recorder {
+	sender: Option<SyncSender<Teximg>>,
+	handle: Option<JoinHandle<()>>,
+	every: u32,
+	frame: u64,
+	// swapchain channel order, swapped to rgba before encoding
+	bgra: bool,
+	// captures dropped because the encoder fell behind
+	dropped: u64,
+	// reused by every capture, grown on resize
+	staging: GrowBuffer<u8>,
+	// copied in the last frame, read after its future is dropped
+	pending: Option<(CaptureBuffer, [u32; 2])>,
+}
+
+// stops at the first error, later frames are dropped
+fn encode_loop(
+	format: RecordFormat,
+	receiver: Receiver<Teximg>,
+) -> Result<(), String> {
+	match format {
+		RecordFormat::Png { dir } => {
+			for (idx, image) in receiver.into_iter().enumerate() {
+				image.save(&format!("{}/{:06}.png", dir, idx))?;
+			}
+		}
+		RecordFormat::Gif { path, delay_ms } => {
+			let file = std::fs::File::create(&path)
+				.map_err(|e| format!("{}: {}", path, e))?;
+			let mut encoder = GifEncoder::new(file);
+			encoder
+				.set_repeat(Repeat::Infinite)
+				.map_err(|e| format!("{}", e))?;
+			for image in receiver.into_iter() {
+				let buffer =
+					TexImage::from_vec(image.dim[0], image.dim[1], image.data)
+						.ok_or_else(|| "bad frame size".to_string())?;
+				let delay = Delay::from_numer_denom_ms(delay_ms, 1);
+				encoder
+					.encode_frame(Frame::from_parts(buffer, 0, 0, delay))
+					.map_err(|e| format!("{}", e))?;
+			}
+		}
+	}
+	Ok(())
+}
+
+// only 8 bit rgba/bgra swapchains can be recorded, some(true) = bgra
+pub(crate) fn is_bgra(format: Format) -> Option<bool> {
+	match format {
+		Format::B8G8R8A8_SRGB | Format::B8G8R8A8_UNORM => Some(true),
+		Format::R8G8B8A8_SRGB | Format::R8G8B8A8_UNORM => Some(false),
+		_ => None,
+	}
+}
+
+impl Recorder {
+	// capture every nth rendered frame of a swapchain accepted by is_bgra
+	pub(crate) fn new(format: RecordFormat, every: u32, bgra: bool) -> Self {
+		let (sender, receiver) = sync_channel(QUEUE_LEN);
+		let handle = std::thread::spawn(move || {
+			if let Err(e) = encode_loop(format, receiver) {
+				eprintln!("ERROR: recording: {}", e);
+			}
+		});
+		Self {
+			sender: Some(sender),
+			handle: Some(handle),
+			every: every.max(1),
+			frame: 0,
+			bgra,
+			dropped: 0,
+			staging: GrowBuffer::new(
+				BufferUsage {
+					transfer_dst: true,
+					..BufferUsage::empty()
+				},
+				true,
+			),
+			pending: None,
+		}
+	}
+
+	pub(crate) fn capture(
+		&mut self,
+		image: VkwSwapchainImage,
+		memalloc: &VkwMemAlloc,
+		builder: &mut VkwCommandBuilder,
+		stats: &mut AllocStats,
+	) {
+		let frame = self.frame;
+		self.frame += 1;
+		if !frame.is_multiple_of(self.every as u64) {
+			return;
+		}
+		let dim = image.dimensions().width_height();
+		let len = (dim[0] * dim[1] * 4) as usize;
+		let buffer = self.staging.reserve(len, memalloc, stats);
+		builder
+			.copy_image_to_buffer(CopyImageToBufferInfo::image_buffer(
+				image,
+				buffer.clone(),
+			))
+			.unwrap();
+		self.pending = Some((buffer, dim));
+	}
+
+	// must be called after the capturing frame has finished
+	pub(crate) fn collect(&mut self) {
+		let (buffer, dim) = match self.pending.take() {
+			Some(x) => x,
+			None => return,
+		};
+		let len = (dim[0] * dim[1] * 4) as usize;
+		let mut data = match buffer.read() {
+			Ok(x) => x[..len].to_vec(),
+			Err(e) => {
+				eprintln!("ERROR: recording: capture not readable: {}", e);
+				return;
+			}
+		};
+		if self.bgra {
+			for pixel in data.chunks_exact_mut(4) {
+				pixel.swap(0, 2);
+			}
+		}
+		if let Some(sender) = self.sender.as_ref() {
+			// a disconnected encoder has already reported its error
+			if let Err(TrySendError::Full(_)) =
+				sender.try_send(Teximg { dim, data })
+			{
+				self.dropped += 1;
+			}
+		}
+	}
+}
+
+// wait for encoding to finish
+impl Drop for Recorder {
+	fn drop(&mut self) {
+		self.collect();
+		self.sender = None;
+		if self.dropped > 0 {
+			eprintln!("ERROR: recording dropped {} frames", self.dropped);
+		}
+		if let Some(handle) = self.handle.take() {
+			if handle.join().is_err() {
+				eprintln!("ERROR: recording thread panicked");
+			}
+		}
+	}
+}
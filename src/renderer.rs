@@ -1,3 +1,7 @@
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::sync::mpsc::{channel, Receiver};
 use std::sync::Arc;
 use vulkano::command_buffer::{AutoCommandBufferBuilder, CommandBufferUsage};
 use vulkano::image::ImageAccess;
@@ -8,26 +12,52 @@ use vulkano::instance::debug::{
 use vulkano::pipeline::graphics::viewport::Viewport;
 use vulkano::swapchain::SwapchainPresentInfo;
 use vulkano::swapchain::{
-	self, AcquireError, SwapchainCreateInfo, SwapchainCreationError,
+	self, AcquireError, ColorSpace, SwapchainCreateInfo,
+	SwapchainCreationError,
 };
 use vulkano::sync::{self, GpuFuture};
 use winit::event_loop::EventLoopWindowTarget;
-use winit::window::Window;
+use winit::window::{CursorIcon, Fullscreen, Icon, Window};
 
-use crate::base::Base;
-use crate::camera::Camera;
+use crate::base::{winit_size, Base};
+use crate::color::ColorConfig;
+use crate::compman::ComputeBinding;
+use crate::depth::DepthConfig;
 use crate::helper::*;
-use crate::model::cmodel::Model;
+use crate::materman::MaterialParams;
+use crate::model::cmodel::{Instance, Model};
+use crate::model::model_id::ModelId;
 use crate::model::model_ref::ModelRef;
+use crate::pool::AllocStats;
+use crate::recorder::{self, RecordFormat, Recorder};
 use crate::rmod::Rmod;
+#[cfg(feature = "glsl")]
+use crate::shader;
+#[cfg(feature = "glsl")]
+use crate::shader::reload::ShaderReloader;
+use crate::submit::{SubmitId, Submission, Submitter};
+use crate::texman::Texman;
 use crate::teximg::Teximg;
+use crate::vertex::InstanceData;
+use crate::view::View;
 use crate::M4;
 
 pub struct Renderer {
 	base: Base,
 	rmod: Rmod,
 	viewport: Viewport,
+	line_width: f32,
+	point_size: f32,
+	color: ColorConfig,
 	dirty: bool,
+	// scene changed since last render, shared with model refs
+	changed: Rc<Cell<bool>>,
+	last_views: Vec<View>,
+	recorder: Option<Recorder>,
+	submitter: Submitter,
+	submissions: Receiver<Submission>,
+	// models inserted through submitter are kept alive here
+	submitted: HashMap<SubmitId, ModelRef>,
 	future: Option<VkwFuture>,
 	_debug_callback: Option<DebugUtilsMessenger>,
 }
@@ -35,17 +65,51 @@ pub struct Renderer {
 // texman
 impl Renderer {
 	pub fn upload_tex(&mut self, image: Teximg, id: i32) {
+		self.submit_upload(|texman, memalloc, builder, stats| {
+			texman.upload(image, id, memalloc, builder, stats)
+		});
+	}
+
+	// linear rgba floats, for hdr content
+	pub fn upload_tex_f32(&mut self, dim: [u32; 2], data: Vec<f32>, id: i32) {
+		if data.len() != (4 * dim[0] * dim[1]) as usize {
+			eprintln!(
+				"ERROR: f32 texture {} has {} floats, expected {}",
+				id,
+				data.len(),
+				4 * dim[0] * dim[1],
+			);
+			return;
+		}
+		self.submit_upload(|texman, memalloc, builder, stats| {
+			texman.upload_f32(dim, data, id, memalloc, builder, stats)
+		});
+	}
+
+	fn submit_upload(
+		&mut self,
+		f: impl FnOnce(
+			&mut Texman,
+			VkwMemAlloc,
+			&mut VkwCommandBuilder,
+			&mut AllocStats,
+		),
+	) {
+		// the staging buffers are reused, wait for the last upload
+		if let Some(future) = self.future.take() {
+			drop(future);
+		}
 		let mut builder = AutoCommandBufferBuilder::primary(
 			&self.base.comalloc,
 			self.base.queue.queue_family_index(),
 			CommandBufferUsage::OneTimeSubmit,
 		)
 		.unwrap();
-		self.rmod.texman.upload(
-			image,
-			id,
+		f(
+			&mut self.rmod.texman,
 			self.base.memalloc.clone(),
 			&mut builder,
+			&mut self.rmod.stats,
 		);
 		let command_buffer = Box::new(builder.build().unwrap());
 		let future = sync::now(self.base.device.clone())
@@ -54,28 +118,310 @@ impl Renderer {
 			.then_signal_fence_and_flush()
 			.unwrap();
 		self.future = Some(future.boxed());
+		self.changed.set(true);
 	}
 
 	pub fn remove_tex(&mut self, outer: i32) {
 		self.rmod.texman.remove(outer);
+		self.changed.set(true);
+	}
+}
+
+// window
+impl Renderer {
+	pub fn set_title(&self, title: &str) {
+		self.get_window().set_title(title);
+	}
+
+	pub fn set_fullscreen(&mut self, fullscreen: bool) {
+		let mode = if fullscreen {
+			Some(Fullscreen::Borderless(None))
+		} else {
+			None
+		};
+		self.get_window().set_fullscreen(mode);
+		self.damage();
+	}
+
+	pub fn is_fullscreen(&self) -> bool {
+		self.get_window().fullscreen().is_some()
+	}
+
+	pub fn set_cursor_icon(&self, cursor: CursorIcon) {
+		self.get_window().set_cursor_icon(cursor);
+	}
+
+	// logical pixels, like the initial size
+	pub fn set_min_size(&mut self, size: Option<[u32; 2]>) {
+		self.get_window().set_min_inner_size(size.map(winit_size));
+		self.damage();
+	}
+
+	pub fn set_size(&mut self, size: [u32; 2]) {
+		self.get_window().set_inner_size(winit_size(size));
+		self.damage();
+	}
+
+	pub fn set_window_icon(&self, icon: Option<&Teximg>) {
+		let icon = match icon {
+			None => None,
+			Some(image) => {
+				let [w, h] = image.dim;
+				match Icon::from_rgba(image.data.clone(), w, h) {
+					Ok(icon) => Some(icon),
+					Err(e) => {
+						eprintln!("ERROR: bad window icon: {}", e);
+						return;
+					}
+				}
+			}
+		};
+		self.get_window().set_window_icon(icon);
+	}
+}
+
+// recorder
+impl Renderer {
+	// capture every nth rendered frame, encoded on a background thread
+	// frames are dropped and counted while the encoder is behind
+	pub fn start_recording(&mut self, format: RecordFormat, every: u32) {
+		self.stop_recording();
+		if !self.base.swapchain.image_usage().transfer_src {
+			eprintln!("ERROR: swapchain without transfer_src, not recording");
+			return;
+		}
+		let image_format = self.base.swapchain.image_format();
+		let bgra = match recorder::is_bgra(image_format) {
+			Some(x) => x,
+			None => {
+				eprintln!("ERROR: cannot record {:?} swapchain", image_format);
+				return;
+			}
+		};
+		self.recorder = Some(Recorder::new(format, every, bgra));
+	}
+
+	// blocks until all captured frames are written
+	pub fn stop_recording(&mut self) {
+		if let Some(future) = self.future.take() {
+			drop(future);
+		}
+		self.recorder = None;
+	}
+}
+
+// the pending capture can only be read after its frame has finished
+impl Drop for Renderer {
+	fn drop(&mut self) {
+		self.stop_recording();
+	}
+}
+
+// submit
+impl Renderer {
+	pub fn get_submitter(&self) -> Submitter {
+		self.submitter.clone()
+	}
+
+	pub fn get_submitted(&self, id: SubmitId) -> Option<ModelRef> {
+		self.submitted.get(&id).cloned()
+	}
+
+	fn drain_submissions(&mut self) {
+		while let Ok(submission) = self.submissions.try_recv() {
+			match submission {
+				Submission::InsertModel(id, model) => {
+					let model = self.insert_model(&model);
+					self.submitted.insert(id, model);
+				}
+				// a removed model stays removed
+				Submission::UpdateModel(id, model) => {
+					match self.submitted.get(&id).cloned() {
+						Some(old) => self.update_model(&old, &model),
+						None => eprintln!("ERROR: update of removed {:?}", id),
+					}
+				}
+				Submission::RemoveModel(id) => {
+					if let Some(model) = self.submitted.remove(&id) {
+						self.remove_model(model.id());
+					}
+				}
+				Submission::UploadTex(image, id) => self.upload_tex(image, id),
+				Submission::RemoveTex(id) => {
+					if self.rmod.texman.try_remove(id) {
+						self.changed.set(true);
+					} else {
+						eprintln!("ERROR: removal of unknown texture {}", id);
+					}
+				}
+			}
+		}
+	}
+}
+
+// materman
+// a material is a fragment shader with this interface:
+// layout(location = 0) in vec4 f_color;
+// layout(location = 1) in vec2 f_tex_coord;
+// layout(location = 2) flat in int f_tex_layer;
+// layout(location = 3) in vec4 f_tint; // optional, model * instance tint
+// layout(set = 1, binding = 0) uniform sampler2D tex[];
+// layout(set = 2, binding = 0) uniform Material { vec4 params[4]; };
+// the output is linear, exposure, tonemap and pq are applied by output.glsl
+// optional, output_params.z is the srgb vertex color flag, see frag.glsl:
+// layout(push_constant) uniform Push { vec4 wire_color; vec4 output_params; };
+impl Renderer {
+	/// # Safety
+	/// words must be a valid spir-v fragment shader, it is not validated
+	pub unsafe fn register_material_spirv(
+		&mut self,
+		id: i32,
+		words: &[u32],
+	) -> Result<(), String> {
+		self.changed.set(true);
+		self.rmod
+			.materman
+			.register_spirv(self.base.device.clone(), id, words)
+	}
+
+	#[cfg(feature = "glsl")]
+	pub fn register_material_glsl(
+		&mut self,
+		id: i32,
+		source: &str,
+	) -> Result<(), String> {
+		let words = shader::compile_glsl(
+			source,
+			shaderc::ShaderKind::Fragment,
+			&format!("material{}", id),
+		)?;
+		// safety: produced by shaderc
+		unsafe { self.register_material_spirv(id, &words) }
+	}
+
+	pub fn set_material_params(&mut self, id: i32, params: [[f32; 4]; 4]) {
+		self.rmod
+			.materman
+			.set_params(id, MaterialParams { data: params });
+		self.changed.set(true);
+	}
+
+	pub fn remove_material(&mut self, id: i32) {
+		self.rmod.materman.remove(id);
+		self.changed.set(true);
+	}
+}
+
+// compman
+// a compute pass is dispatched every frame before drawing,
+// resources are bound in order at set 0:
+// layout(set = 0, binding = n) buffer Vertices { float data[]; };
+// 11 floats per vertex, see vertex.rs:
+// vec4 pos, vec4 color, vec2 tex_coord, int tex_layer (float bits)
+// positions are in model space, transforms and instances are applied later
+// triangle corners with identical content share one vertex through
+// the index buffer, which is not bound
+// layout(set = 0, binding = n, rgba8) uniform image2D image;
+// optional, may declare only some of the words:
+// layout(push_constant) uniform Push { uint vertex_count; uint frame; };
+// vertex_count is the end of the used part of the buffer, it includes
+// holes left by removed or shrunk models, their contents are undefined
+impl Renderer {
+	// can be used as a normal texture by id
+	pub fn create_storage_tex(&mut self, dim: [u32; 2], id: i32) {
+		self.rmod.texman.create_storage(
+			dim,
+			id,
+			self.base.memalloc.clone(),
+			self.base.queue.queue_family_index(),
+		);
+		self.changed.set(true);
+	}
+
+	/// # Safety
+	/// words must be a valid spir-v compute shader, it is not validated
+	pub unsafe fn register_compute_spirv(
+		&mut self,
+		id: i32,
+		words: &[u32],
+		bindings: Vec<ComputeBinding>,
+		groups: [u32; 3],
+	) -> Result<(), String> {
+		self.rmod.compman.register_spirv(
+			self.base.device.clone(),
+			id,
+			words,
+			bindings,
+			groups,
+		)
+	}
+
+	#[cfg(feature = "glsl")]
+	pub fn register_compute_glsl(
+		&mut self,
+		id: i32,
+		source: &str,
+		bindings: Vec<ComputeBinding>,
+		groups: [u32; 3],
+	) -> Result<(), String> {
+		let words = shader::compile_glsl(
+			source,
+			shaderc::ShaderKind::Compute,
+			&format!("compute{}", id),
+		)?;
+		// safety: produced by shaderc
+		unsafe { self.register_compute_spirv(id, &words, bindings, groups) }
+	}
+
+	pub fn set_compute_groups(&mut self, id: i32, groups: [u32; 3]) {
+		self.rmod.compman.set_groups(id, groups);
+	}
+
+	pub fn remove_compute(&mut self, id: i32) {
+		self.rmod.compman.remove(id);
+		self.changed.set(true);
 	}
 }
 
 impl Renderer {
 	pub fn new<E>(el: &EventLoopWindowTarget<E>) -> Self {
-		let base = Base::new(el);
-		let rmod = Rmod::new(base.clone());
+		Self::with_output(el, false)
+	}
+
+	// hdr swapchain if available, tone mapped sdr otherwise
+	pub fn new_hdr<E>(el: &EventLoopWindowTarget<E>) -> Self {
+		let mut result = Self::with_output(el, true);
+		result.color.tonemap = !result.is_hdr();
+		result
+	}
+
+	fn with_output<E>(el: &EventLoopWindowTarget<E>, hdr: bool) -> Self {
+		let base = Base::new(el, hdr);
+		let changed = Rc::new(Cell::new(true));
+		let rmod = Rmod::new(base.clone(), changed.clone());
+		// physical size of the swapchain, not the logical window size
+		let [w, h] = base.images[0].dimensions().width_height();
 		let viewport = Viewport {
 			origin: [0.0, 0.0],
-			dimensions: [800.0, 600.0],
+			dimensions: [w as f32, h as f32],
 			depth_range: 0.0..1.0,
 		};
 
+		let (sender, submissions) = channel();
 		let mut result = Self {
 			base,
 			rmod,
 			viewport,
+			line_width: 1.0,
+			point_size: 1.0,
+			color: Default::default(),
 			dirty: false,
+			changed,
+			last_views: Vec::new(),
+			recorder: None,
+			submitter: Submitter::new(sender),
+			submissions,
+			submitted: HashMap::new(),
 			future: None,
 			_debug_callback: None,
 		};
@@ -91,6 +437,14 @@ impl Renderer {
 		self
 	}
 
+	// development mode: recompile vert.glsl and frag.glsl in dir when changed
+	#[cfg(feature = "glsl")]
+	pub fn with_shader_reload(mut self, dir: &str) -> Self {
+		let (vs, fs) = self.rmod.get_vs_fs();
+		self.rmod.reloader = Some(ShaderReloader::new(dir, vs, fs));
+		self
+	}
+
 	fn get_window(&self) -> &Window {
 		self.base
 			.surface
@@ -100,22 +454,163 @@ impl Renderer {
 			.unwrap()
 	}
 
+	// physical pixels
 	pub fn get_size(&self) -> [u32; 2] {
 		self.get_window().inner_size().into()
 	}
 
+	pub fn get_logical_size(&self) -> [f32; 2] {
+		let window = self.get_window();
+		let size = window.inner_size().to_logical::<f32>(window.scale_factor());
+		[size.width, size.height]
+	}
+
+	// read from the window, current inside ScaleFactorChanged
+	pub fn get_scale_factor(&self) -> f64 {
+		self.get_window().scale_factor()
+	}
+
 	pub fn redraw(&mut self) {
 		self.get_window().request_redraw();
 	}
 
 	pub fn damage(&mut self) {
 		self.dirty = true;
+		self.changed.set(true);
+	}
+
+	// camera changes are detected in render
+	// always true in shader reload mode or with compute passes
+	pub fn needs_redraw(&mut self) -> bool {
+		self.drain_submissions();
+		self.rmod.modelman.gc();
+		self.dirty || self.changed.get() || self.rmod.is_animated()
 	}
 
+	// vulkan objects created by the renderer so far
+	pub fn get_alloc_stats(&self) -> AllocStats {
+		self.rmod.stats
+	}
+
+	pub fn is_hdr(&self) -> bool {
+		self.base.swapchain.image_color_space() != ColorSpace::SrgbNonLinear
+	}
+
+	pub fn get_color_config(&self) -> ColorConfig {
+		self.color
+	}
+
+	pub fn set_color_config(&mut self, color: ColorConfig) {
+		self.color = color;
+		self.changed.set(true);
+	}
+
+	pub fn get_depth_config(&self) -> DepthConfig {
+		self.rmod.get_depth_config()
+	}
+
+	// for 3d content, see depth::perspective and ModelRef::set_depth
+	pub fn set_depth_config(&mut self, depth: DepthConfig) {
+		self.rmod.set_depth_config(depth, &self.base.images);
+		self.changed.set(true);
+	}
+
+	// clamped to device range, 1.0 without wide_lines
+	pub fn set_line_width(&mut self, width: f32) {
+		let device = &self.base.device;
+		if !device.enabled_features().wide_lines {
+			return;
+		}
+		let [min, max] = device.physical_device().properties().line_width_range;
+		self.line_width = width.clamp(min, max);
+		self.changed.set(true);
+	}
+
+	// clamped to device range, 1.0 without large_points
+	pub fn set_point_size(&mut self, size: f32) {
+		let device = &self.base.device;
+		if !device.enabled_features().large_points {
+			return;
+		}
+		let [min, max] = device.physical_device().properties().point_size_range;
+		self.point_size = size.clamp(min, max);
+		self.changed.set(true);
+	}
+
+	// draw triangle edges on top of the scene
+	pub fn set_debug_wireframe(&mut self, enabled: bool) {
+		self.rmod.wireframe = enabled;
+		self.changed.set(true);
+	}
+
+	// a different color for each model
+	pub fn set_debug_wireframe_colored(&mut self, colored: bool) {
+		self.rmod.wireframe_colored = colored;
+		self.changed.set(true);
+	}
+
+	// drawn until remove_model, or until dropped with ModelRef::into_guard
 	pub fn insert_model(&mut self, model: &Model) -> ModelRef {
 		self.rmod.modelman.insert(model, &self.rmod.texman.mapper)
 	}
 
+	// none after removal
+	pub fn get_model(&self, id: ModelId) -> Option<ModelRef> {
+		self.rmod.modelman.get(id).cloned()
+	}
+
+	// live models in insertion order
+	pub fn models(&self) -> impl Iterator<Item = &ModelRef> {
+		self.rmod.modelman.iter()
+	}
+
+	// false if already removed, refs to the model stay usable but unseen
+	pub fn remove_model(&mut self, id: ModelId) -> bool {
+		self.rmod.modelman.remove(id)
+	}
+
+	// recompile in place, keeps z, visibility, tint and draw order
+	pub fn update_model(&mut self, model_ref: &ModelRef, model: &Model) {
+		let mapper = &self.rmod.texman.mapper;
+		self.rmod.modelman.update(model_ref, model, mapper);
+	}
+
+	// draw the model once per instance in a single draw call
+	// cheap to call every frame, the vertices are not uploaded again
+	pub fn set_instances(
+		&mut self,
+		model: &mut ModelRef,
+		instances: &[Instance],
+	) {
+		let mapper = &self.rmod.texman.mapper;
+		let data = instances
+			.iter()
+			.map(|instance| {
+				let tex_layer = match instance.layer {
+					Some(id) => match mapper.get(&id) {
+						Some(x) => *x,
+						None => {
+							eprintln!("ERROR: texture {} not found", id);
+							-1
+						}
+					},
+					None => -1,
+				};
+				let columns: [[f32; 4]; 4] = instance.transform.into();
+				InstanceData {
+					transform0: columns[0],
+					transform1: columns[1],
+					transform2: columns[2],
+					transform3: columns[3],
+					tint: instance.tint,
+					tex_layer,
+				}
+			})
+			.collect();
+		model.set_instances(Some(data));
+	}
+
+	// in physical pixels
 	pub fn render2(&mut self) {
 		let [w, h]: [u32; 2] = self.get_window().inner_size().into();
 		let [w, h] = [w as f32, h as f32];
@@ -123,7 +618,23 @@ impl Renderer {
 		self.render(camera);
 	}
 
+	// in logical pixels, the unit of FontConfig layout on hidpi screens
+	pub fn render2_logical(&mut self) {
+		let [w, h] = self.get_logical_size();
+		let camera = M4::new_orthographic(0., w, 0., h, 1.0, -1.0);
+		self.render(camera);
+	}
+
 	pub fn render(&mut self, camera: M4) {
+		let [w, h] = self.viewport.dimensions;
+		self.render_views(&[View::new([0., 0., w, h], camera)]);
+	}
+
+	// no-op if nothing changed since last frame
+	pub fn render_views(&mut self, views: &[View]) {
+		if !self.needs_redraw() && views == self.last_views.as_slice() {
+			return;
+		}
 		if self.dirty {
 			self.create_swapchain();
 			self.dirty = false;
@@ -149,14 +660,29 @@ impl Renderer {
 		if let Some(future) = self.future.take() {
 			drop(future);
 		}
-		self.rmod.build_command(
+		if let Some(recorder) = self.recorder.as_mut() {
+			recorder.collect();
+		}
+		let complete = self.rmod.build_command(
 			&mut builder,
 			image_num as usize,
-			Camera {
-				data: camera.into(),
-			},
-			self.viewport.clone(),
+			views,
+			self.point_size,
+			self.line_width,
+			self.color.push_data(
+				self.base.swapchain.image_color_space()
+					== ColorSpace::Hdr10St2084,
+			),
 		);
+		// skip frames that did not draw the scene
+		if let Some(recorder) = self.recorder.as_mut().filter(|_| complete) {
+			recorder.capture(
+				self.base.images[image_num as usize].clone(),
+				&self.base.memalloc,
+				&mut builder,
+				&mut self.rmod.stats,
+			);
+		}
 		let command_buffer = Box::new(builder.build().unwrap());
 
 		let future = sync::now(self.base.device.clone())
@@ -173,6 +699,10 @@ impl Renderer {
 			.then_signal_fence_and_flush()
 			.unwrap();
 		self.future = Some(future.boxed());
+		if complete {
+			self.changed.set(false);
+			self.last_views = views.to_vec();
+		}
 	}
 
 	fn create_swapchain(&mut self) {
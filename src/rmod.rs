@@ -1,64 +1,211 @@
-use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer};
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::rc::Rc;
 use vulkano::command_buffer::{RenderPassBeginInfo, SubpassContents};
 use vulkano::descriptor_set::layout::{
-	DescriptorSetLayout, DescriptorSetLayoutCreateInfo,
-	DescriptorSetLayoutCreationError, DescriptorType,
+	DescriptorSetLayout, DescriptorSetLayoutBinding,
+	DescriptorSetLayoutCreateInfo, DescriptorSetLayoutCreationError,
+	DescriptorType,
 };
 use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
 use vulkano::format::Format;
 use vulkano::image::view::ImageView;
 use vulkano::image::{AttachmentImage, ImageAccess};
 use vulkano::pipeline::graphics::color_blend::ColorBlendState;
-use vulkano::pipeline::graphics::depth_stencil::DepthStencilState;
+use vulkano::pipeline::graphics::depth_stencil::{
+	CompareOp, DepthState, DepthStencilState,
+};
 use vulkano::pipeline::graphics::input_assembly::{
 	InputAssemblyState, PrimitiveTopology,
 };
+use vulkano::pipeline::graphics::rasterization::{
+	CullMode, PolygonMode, RasterizationState,
+};
 use vulkano::pipeline::graphics::vertex_input::BuffersDefinition;
-use vulkano::pipeline::graphics::viewport::Viewport;
 use vulkano::pipeline::graphics::viewport::ViewportState;
-use vulkano::pipeline::layout::{PipelineLayout, PipelineLayoutCreateInfo};
+use vulkano::pipeline::graphics::viewport::{Scissor, Viewport};
+use vulkano::pipeline::graphics::GraphicsPipelineCreationError;
+use vulkano::pipeline::layout::{
+	PipelineLayout, PipelineLayoutCreateInfo, PushConstantRange,
+};
 use vulkano::pipeline::GraphicsPipeline;
-use vulkano::pipeline::{Pipeline, PipelineBindPoint};
+use vulkano::pipeline::{Pipeline, PipelineBindPoint, StateMode};
 use vulkano::render_pass::{Framebuffer, FramebufferCreateInfo, Subpass};
+use vulkano::shader::ShaderStages;
 
 use crate::base::Base;
 use crate::camera::Camera;
+use crate::compman::Compman;
+use crate::depth::{DepthConfig, DepthFormat};
 use crate::helper::*;
-use crate::model::modelman::Modelman;
+use crate::materman::{MaterialParams, Materman};
+use crate::model::compiled_model::RenderFlags;
+use crate::model::modelman::{DrawCall, Modelman, Topology};
+use crate::pool::{AllocStats, UniformPool};
 use crate::shader;
+#[cfg(feature = "glsl")]
+use crate::shader::reload::ShaderReloader;
 use crate::texman::Texman;
-use crate::vertex::VertexTex;
+use crate::vertex::{InstanceData, VertexTex};
+use crate::view::View;
 
 pub struct Rmod {
 	base: Base,
 	framebuffers_tex: Vec<VkwFramebuffer>,
-	pipeline_tex: VkwPipeline,
+	// linear scene, read by the output subpass
+	scene: VkwImageView,
+	pipeline_output: VkwPipeline,
+	// none = rebuild, after framebuffers or the render pass change
+	output_set: Option<VkwDescriptorSet>,
+	pipeline_layout: VkwPipelineLayout,
+	// built on first use, except defaults
+	// none = failed to build, reported once
+	pipelines_tex: HashMap<PipelineKey, Option<VkwPipeline>>,
+	depth: DepthConfig,
+	pipeline_wire: VkwPipeline,
+	pub wireframe: bool,
+	pub wireframe_colored: bool,
 	renderpass_tex: VkwRenderPass,
+	vs: VkwShaderModule,
+	fs: VkwShaderModule,
+	#[cfg(feature = "glsl")]
+	pub reloader: Option<ShaderReloader>,
 	pub texman: Texman,
 	pub modelman: Modelman,
+	pub materman: Materman,
+	pub compman: Compman,
 	texset: Option<VkwTextureSet>,
+	camera_pool: UniformPool<Camera>,
+	material_pool: UniformPool<MaterialParams>,
+	pub stats: AllocStats,
+}
+
+// pipelines differ by topology and model flags
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct PipelineKey {
+	pub topology: Topology,
+	pub flags: RenderFlags,
+}
+
+// shared by builtin and material pipelines
+#[derive(Clone)]
+pub struct PipelineBase {
+	pub render_pass: VkwRenderPass,
+	pub device: VkwDevice,
+	pub layout: VkwPipelineLayout,
+	pub vs: VkwShaderModule,
+	pub reverse_z: bool,
 }
 
 impl Rmod {
-	pub fn new(base: Base) -> Self {
+	pub fn new(base: Base, damage: Rc<Cell<bool>>) -> Self {
 		let device = base.device.clone();
-		let renderpass_tex =
-			get_render_pass_clear(device.clone(), base.swapchain.clone());
-		let pipeline_tex = get_pipeline_tex(renderpass_tex.clone(), device, 1);
-		let framebuffers_tex = window_size_dependent_setup(
+		let depth = DepthConfig::default();
+		let renderpass_tex = get_render_pass_clear(
+			device.clone(),
+			base.swapchain.clone(),
+			depth.vk_format(),
+		);
+		let vs = shader::vs::load(device.clone()).unwrap();
+		let fs = shader::fs::load(device.clone()).unwrap();
+		let tex_capacity = get_tex_capacity(&device);
+		let pipeline_layout = get_pipeline_layout(device.clone(), tex_capacity);
+		let pipelines_tex = get_pipelines_tex(
+			&PipelineBase {
+				render_pass: renderpass_tex.clone(),
+				device: device.clone(),
+				layout: pipeline_layout.clone(),
+				vs: vs.clone(),
+				reverse_z: depth.reverse_z,
+			},
+			fs.clone(),
+		)
+		.unwrap();
+		let pipeline_wire = get_pipeline_wire(
+			renderpass_tex.clone(),
+			device.clone(),
+			pipeline_layout.clone(),
+			vs.clone(),
+		);
+		let pipeline_output =
+			get_pipeline_output(renderpass_tex.clone(), device);
+		let (framebuffers_tex, scene) = window_size_dependent_setup(
 			renderpass_tex.clone(),
 			&base.images,
 			base.memalloc.clone(),
+			depth.vk_format(),
 		);
 		let memalloc = base.memalloc.clone();
 		Self {
 			base,
 			framebuffers_tex,
-			pipeline_tex,
+			scene,
+			pipeline_output,
+			output_set: None,
+			pipeline_layout,
+			pipelines_tex,
+			depth,
+			pipeline_wire,
+			wireframe: false,
+			wireframe_colored: false,
 			renderpass_tex,
-			texman: Default::default(),
-			modelman: Modelman::new(memalloc),
+			vs,
+			fs,
+			#[cfg(feature = "glsl")]
+			reloader: None,
+			texman: Texman::new(tex_capacity as usize),
+			modelman: Modelman::new(memalloc, damage),
+			materman: Default::default(),
+			compman: Default::default(),
 			texset: None,
+			camera_pool: Default::default(),
+			material_pool: Default::default(),
+			stats: Default::default(),
+		}
+	}
+
+	fn pipeline_base(&self) -> PipelineBase {
+		PipelineBase {
+			render_pass: self.renderpass_tex.clone(),
+			device: self.base.device.clone(),
+			layout: self.pipeline_layout.clone(),
+			vs: self.vs.clone(),
+			reverse_z: self.depth.reverse_z,
+		}
+	}
+
+	// keep last good pipeline on failure
+	#[cfg(feature = "glsl")]
+	fn reload_shaders(&mut self) {
+		let device = self.base.device.clone();
+		let (vs, fs) = match self.reloader.as_mut() {
+			Some(reloader) => match reloader.poll(device) {
+				Some(x) => x,
+				None => return,
+			},
+			None => return,
+		};
+		let base = PipelineBase {
+			vs: vs.clone(),
+			..self.pipeline_base()
+		};
+		match get_pipelines_tex(&base, fs.clone()) {
+			Ok(pipelines) => {
+				if let Some(reloader) = self.reloader.as_mut() {
+					reloader.accept(vs.clone(), fs.clone());
+				}
+				self.pipelines_tex = pipelines;
+				self.pipeline_wire = get_pipeline_wire(
+					self.renderpass_tex.clone(),
+					self.base.device.clone(),
+					self.pipeline_layout.clone(),
+					vs.clone(),
+				);
+				self.vs = vs;
+				self.fs = fs;
+				self.materman.clear_pipelines();
+			}
+			Err(e) => eprintln!("ERROR: shader reload: {}", e),
 		}
 	}
 
@@ -66,56 +213,53 @@ impl Rmod {
 		&mut self,
 		builder: &mut VkwCommandBuilder,
 		image_num: usize,
-		camera: Camera,
-		viewport: Viewport,
-	) {
+		views: &[View],
+		point_size: f32,
+		line_width: f32,
+		color: [f32; 4],
+	) -> bool {
+		#[cfg(feature = "glsl")]
+		self.reload_shaders();
 		if self.texman.get_dirty() {
 			let (tex_len, update_mapper) = self.texman.tex_len();
 			if tex_len == 0 {
-				return;
+				return false;
 			}
 			self.modelman.map_tex(update_mapper);
-			self.pipeline_tex = get_pipeline_tex(
-				self.renderpass_tex.clone(),
-				self.base.device.clone(),
-				tex_len as u32,
-			);
-			let layout =
-				self.pipeline_tex.layout().set_layouts().get(1).unwrap();
+			let layout = self.pipeline_layout.set_layouts().get(1).unwrap();
 			let texset = self.texman.compile_set(
 				self.base.device.clone(),
 				self.base.dstalloc.clone(),
 				layout.clone(),
+				&mut self.stats,
 			);
 			self.texset = texset;
 		}
 		// dirty workaround for gpulock
-		let count = match self.modelman.write_buffer() {
-			Some(count) => count,
-			None => return,
-		};
-
-		let uniform_buffer = CpuAccessibleBuffer::from_data(
-			&self.base.memalloc,
-			BufferUsage {
-				uniform_buffer: true,
-				..BufferUsage::empty()
-			},
-			false,
-			camera,
-		)
-		.unwrap();
-
-		let layout = self.pipeline_tex.layout().set_layouts().get(0).unwrap();
-		let set = PersistentDescriptorSet::new(
-			&self.base.dstalloc,
-			layout.clone(),
-			[WriteDescriptorSet::buffer(0, uniform_buffer)],
-		)
-		.unwrap();
+		if self.modelman.write_buffer(&mut self.stats).is_none() {
+			return false;
+		}
 
 		let texset = self.texset.clone().unwrap();
-		let clear_values = vec![Some([0.0; 4].into()), Some(1f32.into())];
+		// previous frame has finished, see Renderer::render_views
+		self.camera_pool.rewind();
+		self.material_pool.rewind();
+		self.compman.dispatch(
+			builder,
+			&self.modelman,
+			&self.texman,
+			&self.base.dstalloc,
+			&mut self.stats,
+		);
+		if self.wireframe && !self.compman.is_empty() {
+			self.modelman.invalidate_edges();
+		}
+		// swapchain image, depth, scene
+		let clear_values = vec![
+			None,
+			Some(self.depth.clear_value().into()),
+			Some([0.0; 4].into()),
+		];
 		builder
 			.begin_render_pass(
 				RenderPassBeginInfo {
@@ -126,41 +270,301 @@ impl Rmod {
 				},
 				SubpassContents::Inline,
 			)
-			.unwrap()
-			.set_viewport(0, [viewport]);
+			.unwrap();
+		for view in views.iter() {
+			let [x, y, w, h] = view.rect;
+			builder.set_viewport(
+				0,
+				[Viewport {
+					origin: [x, y],
+					dimensions: [w, h],
+					depth_range: 0.0..1.0,
+				}],
+			);
+			// wide lines and large points may reach past the viewport
+			let [x0, y0] = [x.max(0.0).round(), y.max(0.0).round()];
+			let [x1, y1] = [(x + w).round(), (y + h).round()];
+			builder.set_scissor(
+				0,
+				[Scissor {
+					origin: [x0 as u32, y0 as u32],
+					dimensions: [
+						(x1 - x0).max(0.0) as u32,
+						(y1 - y0).max(0.0) as u32,
+					],
+				}],
+			);
+			let camera = Camera {
+				data: view.camera.into(),
+				point_size,
+			};
+			self.draw_view(
+				builder,
+				camera,
+				texset.clone(),
+				view.mask,
+				line_width,
+				color,
+			);
+		}
+		builder.next_subpass(SubpassContents::Inline).unwrap();
+		self.draw_output(builder, image_num, color);
+		builder.end_render_pass().unwrap();
+		true
+	}
+
+	// exposure, tonemap and pq encode, after everything else is drawn
+	fn draw_output(
+		&mut self,
+		builder: &mut VkwCommandBuilder,
+		image_num: usize,
+		color: [f32; 4],
+	) {
+		let layout = self.pipeline_output.layout().clone();
+		let set = self
+			.output_set
+			.get_or_insert_with(|| {
+				self.stats.descriptor_sets += 1;
+				PersistentDescriptorSet::new(
+					&self.base.dstalloc,
+					layout.set_layouts()[0].clone(),
+					[WriteDescriptorSet::image_view(0, self.scene.clone())],
+				)
+				.unwrap()
+			})
+			.clone();
+		let [w, h] = self.framebuffers_tex[image_num].extent();
+		builder.set_viewport(
+			0,
+			[Viewport {
+				origin: [0.0, 0.0],
+				dimensions: [w as f32, h as f32],
+				depth_range: 0.0..1.0,
+			}],
+		);
+		builder.bind_pipeline_graphics(self.pipeline_output.clone());
+		builder.bind_descriptor_sets(
+			PipelineBindPoint::Graphics,
+			layout.clone(),
+			0,
+			set,
+		);
+		builder.push_constants(layout, 0, color);
+		builder.draw(3, 1, 0, 0).unwrap();
+	}
+
+	fn draw_view(
+		&mut self,
+		builder: &mut VkwCommandBuilder,
+		camera: Camera,
+		texset: VkwTextureSet,
+		mask: u32,
+		line_width: f32,
+		color: [f32; 4],
+	) {
+		let set_layouts = self.pipeline_layout.set_layouts();
+		self.camera_pool.set_layout(set_layouts[0].clone());
+		self.material_pool.set_layout(set_layouts[2].clone());
+		let set = self.camera_pool.next(
+			camera,
+			&self.base.memalloc,
+			&self.base.dstalloc,
+			&mut self.stats,
+		);
 		builder.bind_descriptor_sets(
 			PipelineBindPoint::Graphics,
-			self.pipeline_tex.layout().clone(),
+			self.pipeline_layout.clone(),
 			0,
 			vec![set, texset],
 		);
-		let buffer = self.modelman.buffer.clone();
-		builder.bind_pipeline_graphics(self.pipeline_tex.clone());
-		builder
-			.bind_vertex_buffers(0, buffer)
-			.draw(count as u32, 1, 0, 0)
-			.unwrap();
-		builder.end_render_pass().unwrap();
+		// wire color and output params, see frag.glsl
+		let push_data = [[1f32; 4], color];
+		builder.push_constants(self.pipeline_layout.clone(), 0, push_data);
+		let buffers = (
+			self.modelman.buffer.clone(),
+			self.modelman.instance_buffer.clone(),
+		);
+		builder.bind_vertex_buffers(0, buffers);
+		builder.bind_index_buffer(self.modelman.index_buffer.clone());
+		let pipeline_base = self.pipeline_base();
+		for draw in self.modelman.draws.iter() {
+			if draw.mask & mask == 0 {
+				continue;
+			}
+			// unknown or broken material falls back to builtin
+			let material = draw.material.and_then(|id| {
+				let pipeline = self.materman.get_pipeline(
+					id,
+					draw.flags,
+					&pipeline_base,
+				)?;
+				let set = self.materman.compile_set(
+					id,
+					&mut self.material_pool,
+					&self.base.memalloc,
+					&self.base.dstalloc,
+					&mut self.stats,
+				)?;
+				Some((pipeline, set))
+			});
+			match material {
+				Some((pipeline, set)) => {
+					builder.bind_pipeline_graphics(pipeline);
+					builder.bind_descriptor_sets(
+						PipelineBindPoint::Graphics,
+						self.pipeline_layout.clone(),
+						2,
+						set,
+					);
+				}
+				None => {
+					let key = PipelineKey {
+						topology: draw.topology,
+						flags: draw.flags,
+					};
+					let fs = &self.fs;
+					let pipeline =
+						self.pipelines_tex.entry(key).or_insert_with(|| {
+							try_pipeline_tex(&pipeline_base, fs.clone(), key)
+						});
+					match pipeline {
+						Some(x) => builder.bind_pipeline_graphics(x.clone()),
+						None => continue,
+					};
+					if draw.topology == Topology::Line {
+						builder.set_line_width(line_width);
+					}
+				}
+			}
+			let data = model_push_data(draw);
+			builder.push_constants(self.pipeline_layout.clone(), 32, data);
+			draw_call(builder, draw, draw.topology == Topology::Triangle);
+		}
+		if self.wireframe {
+			self.draw_wireframe(builder, mask);
+		}
+	}
+
+	fn draw_wireframe(&mut self, builder: &mut VkwCommandBuilder, mask: u32) {
+		// index ranges with polygon mode line, vertex ranges otherwise
+		let indexed = self.base.device.enabled_features().fill_mode_non_solid;
+		let instances = self.modelman.instance_buffer.clone();
+		let ranges = if indexed {
+			let buffers = (self.modelman.buffer.clone(), instances);
+			builder.bind_vertex_buffers(0, buffers);
+			builder.bind_index_buffer(self.modelman.index_buffer.clone());
+			self.modelman.ranges.clone()
+		} else {
+			let (buffer, ranges) = match self.modelman.edges(&mut self.stats) {
+				Some(x) => x,
+				None => return,
+			};
+			builder.bind_vertex_buffers(0, (buffer, instances));
+			ranges
+		};
+		builder.bind_pipeline_graphics(self.pipeline_wire.clone());
+		for (idx, draw) in ranges.iter().enumerate() {
+			if draw.mask & mask == 0 {
+				continue;
+			}
+			let color = if self.wireframe_colored {
+				wire_color(idx)
+			} else {
+				[1.0; 4]
+			};
+			// fragment 0..32, vertex 32..112, see get_pipeline_layout
+			builder.push_constants(self.pipeline_layout.clone(), 0, color);
+			let data = model_push_data(draw);
+			builder.push_constants(self.pipeline_layout.clone(), 32, data);
+			draw_call(builder, draw, indexed);
+		}
+	}
+
+	// reloaded shaders and compute passes may change every frame
+	pub fn is_animated(&self) -> bool {
+		#[cfg(feature = "glsl")]
+		if self.reloader.is_some() {
+			return true;
+		}
+		!self.compman.is_empty()
+	}
+
+	#[cfg(feature = "glsl")]
+	pub fn get_vs_fs(&self) -> (VkwShaderModule, VkwShaderModule) {
+		(self.vs.clone(), self.fs.clone())
 	}
 
 	pub fn update_framebuffers(&mut self, images: &VkwImages) {
-		self.framebuffers_tex = window_size_dependent_setup(
+		let (framebuffers, scene) = window_size_dependent_setup(
 			self.renderpass_tex.clone(),
 			images,
 			self.base.memalloc.clone(),
+			self.depth.vk_format(),
+		);
+		self.framebuffers_tex = framebuffers;
+		self.scene = scene;
+		self.output_set = None;
+	}
+
+	pub fn get_depth_config(&self) -> DepthConfig {
+		self.depth
+	}
+
+	// rebuilds render pass and framebuffers, pipelines are rebuilt on use
+	pub fn set_depth_config(
+		&mut self,
+		mut config: DepthConfig,
+		images: &VkwImages,
+	) {
+		if !depth_format_supported(&self.base.device, config.vk_format()) {
+			eprintln!("ERROR: {:?} depth not supported", config.format);
+			config.format = DepthFormat::D16;
+		}
+		self.depth = config;
+		self.renderpass_tex = get_render_pass_clear(
+			self.base.device.clone(),
+			self.base.swapchain.clone(),
+			config.vk_format(),
+		);
+		self.update_framebuffers(images);
+		self.pipeline_wire = get_pipeline_wire(
+			self.renderpass_tex.clone(),
+			self.base.device.clone(),
+			self.pipeline_layout.clone(),
+			self.vs.clone(),
+		);
+		self.pipeline_output = get_pipeline_output(
+			self.renderpass_tex.clone(),
+			self.base.device.clone(),
 		);
+		self.output_set = None;
+		self.pipelines_tex.clear();
+		self.materman.clear_pipelines();
 	}
 }
 
+fn depth_format_supported(device: &VkwDevice, format: Format) -> bool {
+	device
+		.physical_device()
+		.format_properties(format)
+		.map(|x| x.optimal_tiling_features.depth_stencil_attachment)
+		.unwrap_or(false)
+}
+
+// linear and unclamped, so blending happens before the output encode
+const SCENE_FORMAT: Format = Format::R16G16B16A16_SFLOAT;
+
+// the scene is drawn in subpass 0, encoded to the swapchain in subpass 1
 pub fn get_render_pass_clear(
 	device: VkwDevice,
 	swapchain: VkwSwapchain,
+	depth_format: Format,
 ) -> VkwRenderPass {
-	vulkano::single_pass_renderpass!(
+	vulkano::ordered_passes_renderpass!(
 		device,
 		attachments: {
 			color: {
-				load: Clear,
+				load: DontCare,
 				store: Store,
 				format: swapchain.image_format(),
 				samples: 1,
@@ -168,23 +572,84 @@ pub fn get_render_pass_clear(
 			depth: {
 				load: Clear,
 				store: Store,
-				format: Format::D16_UNORM,
+				format: depth_format,
+				samples: 1,
+			},
+			scene: {
+				load: Clear,
+				store: DontCare,
+				format: SCENE_FORMAT,
 				samples: 1,
 			}
 		},
-		pass: {
-			color: [color],
-			depth_stencil: {depth}
-		}
+		passes: [
+			{
+				color: [scene],
+				depth_stencil: {depth},
+				input: []
+			},
+			{
+				color: [color],
+				depth_stencil: {},
+				input: [scene]
+			}
+		]
 	)
 	.unwrap()
 }
 
-pub fn get_pipeline_tex(
-	render_pass: VkwRenderPass,
+const TEX_CAPACITY: u32 = 4096;
+
+// vulkano does not expose partially bound or update after bind flags,
+// so the texture array is variable sized up to a fixed capacity
+// and texture changes only reallocate the set
+fn get_tex_capacity(device: &VkwDevice) -> u32 {
+	let properties = device.physical_device().properties();
+	TEX_CAPACITY
+		.min(properties.max_per_stage_descriptor_samplers)
+		.min(properties.max_per_stage_descriptor_sampled_images)
+		.min(properties.max_descriptor_set_samplers)
+		.min(properties.max_descriptor_set_sampled_images)
+}
+
+// vertices, then instances, see modelman
+fn vertex_input() -> BuffersDefinition {
+	BuffersDefinition::new()
+		.vertex::<VertexTex>()
+		.instance::<InstanceData>()
+}
+
+// model transform and tint, see vert.glsl
+fn model_push_data(draw: &DrawCall) -> [[f32; 4]; 5] {
+	let [c0, c1, c2, c3]: [[f32; 4]; 4] = draw.transform.into();
+	[c0, c1, c2, c3, draw.tint]
+}
+
+fn draw_call(builder: &mut VkwCommandBuilder, draw: &DrawCall, indexed: bool) {
+	let instances = draw.instance_count;
+	if indexed {
+		builder
+			.draw_indexed(
+				draw.count,
+				instances,
+				draw.first,
+				0,
+				draw.first_instance,
+			)
+			.unwrap();
+	} else {
+		builder
+			.draw(draw.count, instances, draw.first, draw.first_instance)
+			.unwrap();
+	}
+}
+
+// set 0: camera, set 1: textures, set 2: material uniform
+// shared by all pipelines so descriptor sets can be bound once
+pub fn get_pipeline_layout(
 	device: VkwDevice,
-	tex_len: u32,
-) -> VkwPipeline {
+	tex_capacity: u32,
+) -> VkwPipelineLayout {
 	let vs = shader::vs::load(device.clone()).unwrap();
 	let fs = shader::fs::load(device.clone()).unwrap();
 	let mut layout_create_infos: Vec<_> =
@@ -196,68 +661,260 @@ pub fn get_pipeline_tex(
 					fs.entry_point("main").unwrap().descriptor_requirements(),
 				),
 		);
-	let mut binding = layout_create_infos[0].bindings.get_mut(&0).unwrap();
+	let binding = layout_create_infos[0].bindings.get_mut(&0).unwrap();
 	binding.descriptor_type = DescriptorType::UniformBuffer;
-	let mut binding = layout_create_infos[1].bindings.get_mut(&0).unwrap();
+	let binding = layout_create_infos[1].bindings.get_mut(&0).unwrap();
 	binding.variable_descriptor_count = true;
-	binding.descriptor_count = tex_len;
+	binding.descriptor_count = tex_capacity;
+	let mut material_info = DescriptorSetLayoutCreateInfo::default();
+	material_info.bindings.insert(
+		0,
+		DescriptorSetLayoutBinding {
+			stages: ShaderStages {
+				fragment: true,
+				..ShaderStages::empty()
+			},
+			..DescriptorSetLayoutBinding::descriptor_type(
+				DescriptorType::UniformBuffer,
+			)
+		},
+	);
+	layout_create_infos.push(material_info);
 	let set_layouts = layout_create_infos
 		.into_iter()
 		.map(|desc| DescriptorSetLayout::new(device.clone(), desc))
 		.collect::<Result<Vec<_>, DescriptorSetLayoutCreationError>>()
 		.unwrap();
-	let pipeline_layout = PipelineLayout::new(
-		device.clone(),
+	// fragment 0..32: wireframe color and output params
+	// vertex 32..112: model transform and tint
+	let push_constant_ranges = vec![
+		PushConstantRange {
+			stages: ShaderStages {
+				fragment: true,
+				..ShaderStages::empty()
+			},
+			offset: 0,
+			size: 32,
+		},
+		PushConstantRange {
+			stages: ShaderStages {
+				vertex: true,
+				..ShaderStages::empty()
+			},
+			offset: 32,
+			size: 80,
+		},
+	];
+	PipelineLayout::new(
+		device,
 		PipelineLayoutCreateInfo {
 			set_layouts,
+			push_constant_ranges,
 			..Default::default()
 		},
 	)
-	.unwrap();
+	.unwrap()
+}
+
+// default flags of each topology, also checks fs after reload
+pub fn get_pipelines_tex(
+	base: &PipelineBase,
+	fs: VkwShaderModule,
+) -> Result<
+	HashMap<PipelineKey, Option<VkwPipeline>>,
+	GraphicsPipelineCreationError,
+> {
+	[Topology::Triangle, Topology::Line, Topology::Point]
+		.into_iter()
+		.map(|topology| {
+			let key = PipelineKey {
+				topology,
+				flags: Default::default(),
+			};
+			Ok((key, Some(get_pipeline_tex(base, fs.clone(), key)?)))
+		})
+		.collect()
+}
+
+// like get_pipeline_tex, but reports the error, for lazy variants
+fn try_pipeline_tex(
+	base: &PipelineBase,
+	fs: VkwShaderModule,
+	key: PipelineKey,
+) -> Option<VkwPipeline> {
+	match get_pipeline_tex(base, fs, key) {
+		Ok(pipeline) => Some(pipeline),
+		Err(e) => {
+			eprintln!("ERROR: pipeline {:?}: {}", key, e);
+			None
+		}
+	}
+}
+
+// without depth test, depth is written unconditionally
+fn get_depth_stencil_state(
+	flags: RenderFlags,
+	reverse_z: bool,
+) -> DepthStencilState {
+	if !flags.depth_test && !flags.depth_write {
+		return DepthStencilState::disabled();
+	}
+	let compare_op = if !flags.depth_test {
+		CompareOp::Always
+	} else if reverse_z {
+		CompareOp::Greater
+	} else {
+		CompareOp::Less
+	};
+	DepthStencilState {
+		depth: Some(DepthState {
+			enable_dynamic: false,
+			write_enable: StateMode::Fixed(flags.depth_write),
+			compare_op: StateMode::Fixed(compare_op),
+		}),
+		..DepthStencilState::disabled()
+	}
+}
+
+pub fn get_pipeline_tex(
+	base: &PipelineBase,
+	fs: VkwShaderModule,
+	key: PipelineKey,
+) -> Result<VkwPipeline, GraphicsPipelineCreationError> {
+	let topology = match key.topology {
+		Topology::Triangle => PrimitiveTopology::TriangleList,
+		Topology::Line => PrimitiveTopology::LineList,
+		Topology::Point => PrimitiveTopology::PointList,
+	};
+	let mut rasterization_state = RasterizationState::new();
+	if topology == PrimitiveTopology::LineList {
+		rasterization_state.line_width = StateMode::Dynamic;
+	}
+	if key.flags.cull_back {
+		rasterization_state = rasterization_state.cull_mode(CullMode::Back);
+	}
+	let subpass = Subpass::from(base.render_pass.clone(), 0).unwrap();
+	GraphicsPipeline::start()
+		.vertex_input_state(vertex_input())
+		.vertex_shader(base.vs.entry_point("main").unwrap(), ())
+		.input_assembly_state(InputAssemblyState::new().topology(topology))
+		.rasterization_state(rasterization_state)
+		.viewport_state(ViewportState::viewport_dynamic_scissor_dynamic(1))
+		.fragment_shader(fs.entry_point("main").unwrap(), ())
+		.depth_stencil_state(get_depth_stencil_state(key.flags, base.reverse_z))
+		.color_blend_state(
+			ColorBlendState::new(subpass.num_color_attachments()).blend_alpha(),
+		)
+		.render_pass(subpass)
+		.with_pipeline_layout(base.device.clone(), base.layout.clone())
+}
 
+// polygon mode line if supported, otherwise drawn from Modelman::build_edges
+// always on top of the filled scene
+pub fn get_pipeline_wire(
+	render_pass: VkwRenderPass,
+	device: VkwDevice,
+	pipeline_layout: VkwPipelineLayout,
+	vs: VkwShaderModule,
+) -> VkwPipeline {
+	let fs = shader::wire_fs::load(device.clone()).unwrap();
+	let (topology, polygon_mode) =
+		if device.enabled_features().fill_mode_non_solid {
+			(PrimitiveTopology::TriangleList, PolygonMode::Line)
+		} else {
+			(PrimitiveTopology::LineList, PolygonMode::Fill)
+		};
 	let subpass = Subpass::from(render_pass, 0).unwrap();
-	let pipeline = GraphicsPipeline::start()
-		.vertex_input_state(BuffersDefinition::new().vertex::<VertexTex>())
+	GraphicsPipeline::start()
+		.vertex_input_state(vertex_input())
 		.vertex_shader(vs.entry_point("main").unwrap(), ())
-		.input_assembly_state(
-			InputAssemblyState::new().topology(PrimitiveTopology::TriangleList),
+		.input_assembly_state(InputAssemblyState::new().topology(topology))
+		.rasterization_state(
+			RasterizationState::new().polygon_mode(polygon_mode),
 		)
-		.viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+		.viewport_state(ViewportState::viewport_dynamic_scissor_dynamic(1))
 		.fragment_shader(fs.entry_point("main").unwrap(), ())
-		.depth_stencil_state(DepthStencilState::simple_depth_test())
+		.depth_stencil_state(DepthStencilState::disabled())
 		.color_blend_state(
 			ColorBlendState::new(subpass.num_color_attachments()).blend_alpha(),
 		)
 		.render_pass(subpass)
 		.with_pipeline_layout(device, pipeline_layout)
-		.unwrap();
-	pipeline
+		.unwrap()
+}
+
+// own layout, see output.glsl
+fn get_pipeline_output(
+	render_pass: VkwRenderPass,
+	device: VkwDevice,
+) -> VkwPipeline {
+	let vs = shader::output_vs::load(device.clone()).unwrap();
+	let fs = shader::output_fs::load(device.clone()).unwrap();
+	GraphicsPipeline::start()
+		.vertex_input_state(BuffersDefinition::new())
+		.vertex_shader(vs.entry_point("main").unwrap(), ())
+		.input_assembly_state(InputAssemblyState::new())
+		.viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+		.fragment_shader(fs.entry_point("main").unwrap(), ())
+		.render_pass(Subpass::from(render_pass, 1).unwrap())
+		.build(device)
+		.unwrap()
+}
+
+// spread hues by golden angle
+fn wire_color(idx: usize) -> [f32; 4] {
+	let h = (idx as f32 * 0.618034).fract() * 6.0;
+	let x = 1.0 - ((h % 2.0) - 1.0).abs();
+	let [r, g, b] = match h as u32 {
+		0 => [1.0, x, 0.0],
+		1 => [x, 1.0, 0.0],
+		2 => [0.0, 1.0, x],
+		3 => [0.0, x, 1.0],
+		4 => [x, 0.0, 1.0],
+		_ => [1.0, 0.0, x],
+	};
+	[r, g, b, 1.0]
 }
 
 pub fn window_size_dependent_setup(
 	render_pass: VkwRenderPass,
 	images: &VkwImages,
 	memalloc: VkwMemAlloc,
-) -> Vec<VkwFramebuffer> {
+	depth_format: Format,
+) -> (Vec<VkwFramebuffer>, VkwImageView) {
 	let dimensions = images[0].dimensions().width_height();
 	let depth_buffer = ImageView::new_default(
-		AttachmentImage::transient(&memalloc, dimensions, Format::D16_UNORM)
+		AttachmentImage::transient(&memalloc, dimensions, depth_format)
 			.unwrap(),
 	)
 	.unwrap();
+	let scene = ImageView::new_default(
+		AttachmentImage::transient_input_attachment(
+			&memalloc,
+			dimensions,
+			SCENE_FORMAT,
+		)
+		.unwrap(),
+	)
+	.unwrap();
 
-	images
+	let framebuffers = images
 		.iter()
 		.map(|image| {
 			let view = ImageView::new_default(image.clone()).unwrap();
 			Framebuffer::new(
 				render_pass.clone(),
 				FramebufferCreateInfo {
-					attachments: vec![view, depth_buffer.clone()],
+					attachments: vec![
+						view,
+						depth_buffer.clone(),
+						scene.clone(),
+					],
 					..Default::default()
 				},
 			)
 			.unwrap()
 		})
-		.collect::<Vec<_>>()
+		.collect::<Vec<_>>();
+	(framebuffers, scene)
 }
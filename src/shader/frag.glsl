@@ -4,16 +4,36 @@
 layout(location = 0) in vec4 f_color;
 layout(location = 1) in vec2 f_tex_coord;
 layout(location = 2) flat in int f_tex_layer;
+layout(location = 3) in vec4 f_tint;
 
 layout(location = 0) out vec4 o_color;
 
 layout(set = 1, binding = 0) uniform sampler2D tex[];
 
+layout(push_constant) uniform Push {
+	vec4 wire_color;
+	// z: srgb vertex color, the rest is applied by output.glsl
+	vec4 output_params;
+} push;
+
+vec3 srgb_to_linear(vec3 c) {
+	return mix(
+		c / 12.92,
+		pow((c + 0.055) / 1.055, vec3(2.4)),
+		step(0.04045, c)
+	);
+}
+
 void main() {
+	vec4 color = f_color;
+	if (push.output_params.z > 0.5) {
+		color.xyz = srgb_to_linear(color.xyz);
+	}
 	if (f_tex_layer >= 0) {
 		o_color = texture(tex[f_tex_layer], f_tex_coord);
 	} else {
 		o_color = vec4(0.0, 0.0, 0.0, 1.0);
 	}
-	o_color.xyz = f_color.w * f_color.xyz + (1.0 - f_color.w) * o_color.xyz;
+	o_color.xyz = color.w * color.xyz + (1.0 - color.w) * o_color.xyz;
+	o_color *= f_tint;
 }
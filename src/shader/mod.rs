@@ -1,3 +1,6 @@
+#[cfg(feature = "glsl")]
+pub mod reload;
+
 pub mod vs {
 	vulkano_shaders::shader! {
 		ty: "vertex",
@@ -13,3 +16,47 @@ pub mod fs {
 		path: "src/shader/frag.glsl",
 	}
 }
+
+pub mod wire_fs {
+	vulkano_shaders::shader! {
+		ty: "fragment",
+		path: "src/shader/wire.glsl",
+	}
+}
+
+// exposure, tonemap and pq encode of the whole scene
+pub mod output_vs {
+	vulkano_shaders::shader! {
+		ty: "vertex",
+		path: "src/shader/fullscreen.glsl",
+	}
+}
+
+pub mod output_fs {
+	vulkano_shaders::shader! {
+		ty: "fragment",
+		path: "src/shader/output.glsl",
+	}
+}
+
+// runtime compilation, target matches fs above
+#[cfg(feature = "glsl")]
+pub fn compile_glsl(
+	source: &str,
+	kind: shaderc::ShaderKind,
+	name: &str,
+) -> Result<Vec<u32>, String> {
+	let compiler =
+		shaderc::Compiler::new().ok_or("cannot create shaderc compiler")?;
+	let mut options = shaderc::CompileOptions::new()
+		.ok_or("cannot create shaderc options")?;
+	options.set_target_env(
+		shaderc::TargetEnv::Vulkan,
+		shaderc::EnvVersion::Vulkan1_2 as u32,
+	);
+	options.set_target_spirv(shaderc::SpirvVersion::V1_5);
+	let artifact = compiler
+		.compile_into_spirv(source, kind, name, "main", Some(&options))
+		.map_err(|e| format!("{}", e))?;
+	Ok(artifact.as_binary().to_vec())
+}
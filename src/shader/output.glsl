@@ -0,0 +1,40 @@
+#version 450
+
+layout(location = 0) out vec4 o_color;
+
+// linear scene color written by frag.glsl, wire.glsl and materials
+layout(input_attachment_index = 0, set = 0, binding = 0)
+	uniform subpassInput scene;
+
+layout(push_constant) uniform Push {
+	// x: exposure, y: tonemap, z: unused, w: pq output
+	vec4 output_params;
+} push;
+
+// hdr10, 1.0 is reference white at 203 nits
+vec3 linear_to_pq(vec3 c) {
+	const mat3 bt709_to_bt2020 = mat3(
+		0.6274, 0.0691, 0.0164,
+		0.3293, 0.9195, 0.0880,
+		0.0433, 0.0114, 0.8956
+	);
+	const float m1 = 0.1593017578125;
+	const float m2 = 78.84375;
+	const float c1 = 0.8359375;
+	const float c2 = 18.8515625;
+	const float c3 = 18.6875;
+	vec3 y = clamp(bt709_to_bt2020 * c * 203.0 / 10000.0, 0.0, 1.0);
+	vec3 p = pow(y, vec3(m1));
+	return pow((c1 + c2 * p) / (1.0 + c3 * p), vec3(m2));
+}
+
+void main() {
+	o_color = subpassLoad(scene);
+	o_color.xyz *= push.output_params.x;
+	if (push.output_params.y > 0.5) {
+		o_color.xyz = o_color.xyz / (1.0 + o_color.xyz);
+	}
+	if (push.output_params.w > 0.5) {
+		o_color.xyz = linear_to_pq(o_color.xyz);
+	}
+}
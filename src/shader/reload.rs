@@ -0,0 +1,87 @@
+use std::path::PathBuf;
+use std::time::SystemTime;
+use vulkano::shader::ShaderModule;
+
+use super::compile_glsl;
+use crate::helper::*;
+
+// development mode: poll vert.glsl and frag.glsl in a directory,
+// recompile at runtime and keep the last good module on error
+pub struct ShaderReloader {
+	dir: PathBuf,
+	mtimes: [Option<SystemTime>; 2],
+	vs: VkwShaderModule,
+	fs: VkwShaderModule,
+}
+
+const FILES: [(&str, shaderc::ShaderKind); 2] = [
+	("vert.glsl", shaderc::ShaderKind::Vertex),
+	("frag.glsl", shaderc::ShaderKind::Fragment),
+];
+
+impl ShaderReloader {
+	pub fn new(dir: &str, vs: VkwShaderModule, fs: VkwShaderModule) -> Self {
+		Self {
+			dir: PathBuf::from(dir),
+			mtimes: [None; 2],
+			vs,
+			fs,
+		}
+	}
+
+	fn compile(
+		&self,
+		device: VkwDevice,
+		name: &str,
+		kind: shaderc::ShaderKind,
+	) -> Result<VkwShaderModule, String> {
+		let path = self.dir.join(name);
+		let source =
+			std::fs::read_to_string(&path).map_err(|e| format!("{}", e))?;
+		let words = compile_glsl(&source, kind, &path.to_string_lossy())?;
+		unsafe { ShaderModule::from_words(device, &words) }
+			.map_err(|e| format!("{}", e))
+	}
+
+	// return candidate (vs, fs) if any file changed and compiled,
+	// the baseline only moves on accept
+	pub fn poll(
+		&mut self,
+		device: VkwDevice,
+	) -> Option<(VkwShaderModule, VkwShaderModule)> {
+		let mut changed = false;
+		let mut vs = self.vs.clone();
+		let mut fs = self.fs.clone();
+		for (idx, (name, kind)) in FILES.into_iter().enumerate() {
+			let mtime = std::fs::metadata(self.dir.join(name))
+				.and_then(|x| x.modified())
+				.ok();
+			if mtime.is_none() || mtime == self.mtimes[idx] {
+				continue;
+			}
+			self.mtimes[idx] = mtime;
+			match self.compile(device.clone(), name, kind) {
+				Ok(module) => {
+					if idx == 0 {
+						vs = module;
+					} else {
+						fs = module;
+					}
+					changed = true;
+				}
+				Err(e) => eprintln!("ERROR: {}: {}", name, e),
+			}
+		}
+		if changed {
+			Some((vs, fs))
+		} else {
+			None
+		}
+	}
+
+	// the candidate from poll built its pipelines
+	pub fn accept(&mut self, vs: VkwShaderModule, fs: VkwShaderModule) {
+		self.vs = vs;
+		self.fs = fs;
+	}
+}
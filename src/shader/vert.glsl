@@ -5,17 +5,40 @@ layout(location = 1) in vec4 color;
 layout(location = 2) in vec2 tex_coord;
 layout(location = 3) in int tex_layer;
 
+// per instance, see vertex.rs
+layout(location = 4) in vec4 transform0;
+layout(location = 5) in vec4 transform1;
+layout(location = 6) in vec4 transform2;
+layout(location = 7) in vec4 transform3;
+layout(location = 8) in vec4 tint;
+layout(location = 9) in int instance_layer;
+
 layout(location = 0) out vec4 f_color;
 layout(location = 1) out vec2 f_tex_coord;
 layout(location = 2) out int f_tex_layer;
+layout(location = 3) out vec4 f_tint;
 
 layout(set = 0, binding = 0) uniform Data {
 	mat4 proj;
+	float point_size;
 } uniforms;
 
+// bytes 0..32 are used by frag.glsl
+layout(push_constant) uniform Push {
+	layout(offset = 32) mat4 model;
+	vec4 tint;
+} push;
+
 void main() {
-	gl_Position = uniforms.proj * pos;
+	mat4 instance = mat4(transform0, transform1, transform2, transform3);
+	gl_Position = uniforms.proj * push.model * instance * pos;
+	gl_PointSize = uniforms.point_size;
 	f_color = color;
 	f_tex_coord = tex_coord;
-	f_tex_layer = tex_layer;
+	if (tex_layer >= 0 && instance_layer >= 0) {
+		f_tex_layer = instance_layer;
+	} else {
+		f_tex_layer = tex_layer;
+	}
+	f_tint = push.tint * tint;
 }
@@ -0,0 +1,11 @@
+#version 450
+
+layout(location = 0) out vec4 o_color;
+
+layout(push_constant) uniform Wire {
+	vec4 color;
+} wire;
+
+void main() {
+	o_color = wire.color;
+}
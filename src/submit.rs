@@ -0,0 +1,62 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+
+use crate::model::cmodel::Model;
+use crate::teximg::Teximg;
+
+// a model inserted through a Submitter
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SubmitId(u64);
+
+pub(crate) enum Submission {
+	InsertModel(SubmitId, Model),
+	UpdateModel(SubmitId, Model),
+	RemoveModel(SubmitId),
+	UploadTex(Teximg, i32),
+	RemoveTex(i32),
+}
+
+// usable from any thread, drained at the start of Renderer::render
+#[derive(Clone)]
+pub struct Submitter {
+	sender: Sender<Submission>,
+	id_alloc: Arc<AtomicU64>,
+}
+
+impl Submitter {
+	pub(crate) fn new(sender: Sender<Submission>) -> Self {
+		Self {
+			sender,
+			id_alloc: Default::default(),
+		}
+	}
+
+	// sending fails only after the renderer is dropped
+	fn send(&self, submission: Submission) {
+		let _ = self.sender.send(submission);
+	}
+
+	pub fn insert_model(&self, model: Model) -> SubmitId {
+		let id = SubmitId(self.id_alloc.fetch_add(1, Ordering::Relaxed));
+		self.send(Submission::InsertModel(id, model));
+		id
+	}
+
+	// ignored once remove_model of the id has been drained
+	pub fn update_model(&self, id: SubmitId, model: Model) {
+		self.send(Submission::UpdateModel(id, model));
+	}
+
+	pub fn remove_model(&self, id: SubmitId) {
+		self.send(Submission::RemoveModel(id));
+	}
+
+	pub fn upload_tex(&self, image: Teximg, id: i32) {
+		self.send(Submission::UploadTex(image, id));
+	}
+
+	pub fn remove_tex(&self, id: i32) {
+		self.send(Submission::RemoveTex(id));
+	}
+}
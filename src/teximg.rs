@@ -44,11 +44,11 @@ impl Teximg {
 		Self::from_image_buffer(image::open(path).unwrap().into_rgba8())
 	}
 
-	pub fn save(&self, path: &str) {
+	pub fn save(&self, path: &str) -> Result<(), String> {
 		TexImage::from_vec(self.dim[0], self.dim[1], self.data.clone())
-			.unwrap()
+			.ok_or_else(|| "data does not match dim".to_string())?
 			.save(path)
-			.unwrap();
+			.map_err(|e| format!("{}", e))
 	}
 }
 
@@ -1,11 +1,17 @@
 use std::collections::HashMap;
+use std::sync::Arc;
+use vulkano::buffer::{BufferContents, BufferUsage};
 use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
 use vulkano::format::Format;
 use vulkano::image::view::{ImageView, ImageViewCreateInfo, ImageViewType};
-use vulkano::image::{ImageDimensions, ImmutableImage, MipmapsCount};
+use vulkano::image::{
+	ImageCreateFlags, ImageDimensions, ImageUsage, ImmutableImage,
+	MipmapsCount, StorageImage,
+};
 use vulkano::sampler::{Sampler, SamplerCreateInfo};
 
 use crate::helper::*;
+use crate::pool::{AllocStats, GrowBuffer};
 use crate::teximg::Teximg;
 
 pub struct Texman {
@@ -21,36 +27,65 @@ pub struct Texman {
 	id_alloc: i32,
 
 	image_views: Vec<VkwImageView>,
+	// by outer id, also present in image_views
+	storage_views: HashMap<i32, VkwStorageView>,
+	// length of the texture array in the pipeline layout
+	capacity: usize,
+	// all views share one sampler
+	sampler: Option<Arc<Sampler>>,
 	dirty: bool,
+	// reused by uploads, one per pixel type
+	staging: GrowBuffer<u8>,
+	staging_f32: GrowBuffer<f32>,
 }
 
-impl Default for Texman {
-	fn default() -> Self {
+fn staging_usage() -> BufferUsage {
+	BufferUsage {
+		transfer_src: true,
+		..BufferUsage::empty()
+	}
+}
+
+impl Texman {
+	pub fn new(capacity: usize) -> Self {
 		Self {
 			mapper: Default::default(),
 			remove_list: Vec::new(),
 			id_alloc: 0,
 			image_views: Vec::new(),
+			storage_views: HashMap::new(),
+			capacity,
+			sampler: None,
 			dirty: true,
+			staging: GrowBuffer::new(staging_usage(), false),
+			staging_f32: GrowBuffer::new(staging_usage(), false),
 		}
 	}
 }
 
 // TODO: mutable image
-fn create_image_view(
-	image: Teximg,
+fn create_image_view<Px>(
+	data: &[Px],
+	dim: [u32; 2],
+	format: Format,
 	memalloc: VkwMemAlloc,
 	builder: &mut VkwCommandBuilder,
-) -> VkwImageView {
+	staging: &mut GrowBuffer<Px>,
+	stats: &mut AllocStats,
+) -> VkwImageView
+where
+	[Px]: BufferContents,
+	Px: Copy + 'static,
+{
 	let dimensions = ImageDimensions::Dim2d {
-		width: image.dim[0],
-		height: image.dim[1],
+		width: dim[0],
+		height: dim[1],
 		array_layers: 1,
 	};
-	let format = Format::R8G8B8A8_SRGB;
-	let image = ImmutableImage::from_iter(
+	let buffer = staging.write(data, &memalloc, stats);
+	let image = ImmutableImage::from_buffer(
 		&memalloc,
-		image.data.into_iter(),
+		buffer,
 		dimensions,
 		MipmapsCount::One,
 		format,
@@ -74,15 +109,91 @@ impl Texman {
 		id: i32,
 		memalloc: VkwMemAlloc,
 		builder: &mut VkwCommandBuilder,
+		stats: &mut AllocStats,
+	) {
+		let image_view = create_image_view(
+			&image.data,
+			image.dim,
+			Format::R8G8B8A8_SRGB,
+			memalloc,
+			builder,
+			&mut self.staging,
+			stats,
+		);
+		self.insert(image_view, id);
+	}
+
+	// linear rgba, not clamped
+	pub fn upload_f32(
+		&mut self,
+		dim: [u32; 2],
+		data: Vec<f32>,
+		id: i32,
+		memalloc: VkwMemAlloc,
+		builder: &mut VkwCommandBuilder,
+		stats: &mut AllocStats,
+	) {
+		let image_view = create_image_view(
+			&data,
+			dim,
+			Format::R32G32B32A32_SFLOAT,
+			memalloc,
+			builder,
+			&mut self.staging_f32,
+			stats,
+		);
+		self.insert(image_view, id);
+	}
+
+	// rgba unorm, writable from compute passes, contents undefined
+	pub fn create_storage(
+		&mut self,
+		dim: [u32; 2],
+		id: i32,
+		memalloc: VkwMemAlloc,
+		queue_family_index: u32,
 	) {
+		let dimensions = ImageDimensions::Dim2d {
+			width: dim[0],
+			height: dim[1],
+			array_layers: 1,
+		};
+		let image = StorageImage::with_usage(
+			&memalloc,
+			dimensions,
+			Format::R8G8B8A8_UNORM,
+			ImageUsage {
+				storage: true,
+				sampled: true,
+				..ImageUsage::empty()
+			},
+			ImageCreateFlags::empty(),
+			[queue_family_index],
+		)
+		.unwrap();
+		let image_view = ImageView::new_default(image).unwrap();
+		if self.insert(image_view.clone(), id) {
+			self.storage_views.insert(id, image_view);
+		}
+	}
+
+	pub fn get_storage(&self, id: i32) -> Option<VkwStorageView> {
+		self.storage_views.get(&id).cloned()
+	}
+
+	fn insert(&mut self, image_view: VkwImageView, id: i32) -> bool {
 		if let Some(id_inner) = self.mapper.get(&id) {
 			self.remove_list.push(*id_inner);
+			self.storage_views.remove(&id);
+		} else if self.mapper.len() >= self.capacity {
+			eprintln!("ERROR: texture capacity {} exceeded", self.capacity);
+			return false;
 		}
-		let image_view = create_image_view(image, memalloc, builder);
 		self.mapper.insert(id, self.id_alloc);
 		self.id_alloc += 1;
 		self.image_views.push(image_view);
 		self.dirty = true;
+		true
 	}
 
 	pub fn tex_len(&mut self) -> (usize, HashMap<i32, i32>) {
@@ -92,9 +203,23 @@ impl Texman {
 
 	pub fn remove(&mut self, outer: i32) {
 		assert!(outer >= 0);
-		let inner = self.mapper.remove(&outer).unwrap();
+		let removed = self.try_remove(outer);
+		assert!(removed, "unknown texture {}", outer);
+	}
+
+	// false if the id is unknown or internal, for ids from other threads
+	pub fn try_remove(&mut self, outer: i32) -> bool {
+		if outer < 0 {
+			return false;
+		}
+		let inner = match self.mapper.remove(&outer) {
+			Some(x) => x,
+			None => return false,
+		};
+		self.storage_views.remove(&outer);
 		self.dirty = true;
 		self.remove_list.push(inner);
+		true
 	}
 
 	pub fn get_dirty(&mut self) -> bool {
@@ -122,27 +247,32 @@ impl Texman {
 	}
 
 	// NOTE: gc is called in tex_len, not called here!
+	// only the set is rebuilt, the layout keeps its capacity
 	pub fn compile_set(
 		&mut self,
 		device: VkwDevice,
 		dstalloc: VkwDstAlloc,
 		layout: VkwTexLayout,
+		stats: &mut AllocStats,
 	) -> Option<VkwTextureSet> {
+		if self.image_views.is_empty() {
+			return None;
+		}
+		let sampler = self
+			.sampler
+			.get_or_insert_with(|| {
+				stats.samplers += 1;
+				Sampler::new(device, SamplerCreateInfo::default()).unwrap()
+			})
+			.clone();
 		let iter: Vec<_> = self
 			.image_views
 			.iter()
 			.cloned()
-			.map(|view| {
-				let sampler =
-					Sampler::new(device.clone(), SamplerCreateInfo::default())
-						.unwrap();
-				(view as _, sampler)
-			})
+			.map(|view| (view as _, sampler.clone()))
 			.collect();
-		if iter.is_empty() {
-			return None;
-		}
 
+		stats.descriptor_sets += 1;
 		Some(
 			PersistentDescriptorSet::new_variable(
 				&dstalloc,
@@ -9,3 +9,40 @@ pub struct VertexTex {
 	pub tex_layer: i32,
 }
 vulkano::impl_vertex!(VertexTex, pos, color, tex_coord, tex_layer);
+
+// per instance input of vert.glsl, see cmodel::Instance
+#[repr(C)]
+#[derive(Zeroable, Pod, Debug, Clone, Copy)]
+pub struct InstanceData {
+	// columns of the transform
+	pub transform0: [f32; 4],
+	pub transform1: [f32; 4],
+	pub transform2: [f32; 4],
+	pub transform3: [f32; 4],
+	pub tint: [f32; 4],
+	// -1 = keep the vertex layer
+	pub tex_layer: i32,
+}
+vulkano::impl_vertex!(
+	InstanceData,
+	transform0,
+	transform1,
+	transform2,
+	transform3,
+	tint,
+	tex_layer
+);
+
+// used by models without instances
+impl Default for InstanceData {
+	fn default() -> Self {
+		Self {
+			transform0: [1.0, 0.0, 0.0, 0.0],
+			transform1: [0.0, 1.0, 0.0, 0.0],
+			transform2: [0.0, 0.0, 1.0, 0.0],
+			transform3: [0.0, 0.0, 0.0, 1.0],
+			tint: [1.0; 4],
+			tex_layer: -1,
+		}
+	}
+}
@@ -0,0 +1,25 @@
+use crate::M4;
+
+// a viewport rectangle in physical pixels with its own camera
+#[derive(Clone, Debug, PartialEq)]
+pub struct View {
+	pub rect: [f32; 4], // x, y, w, h
+	pub camera: M4,
+	// draw models whose view mask intersects this
+	pub mask: u32,
+}
+
+impl View {
+	pub fn new(rect: [f32; 4], camera: M4) -> Self {
+		Self {
+			rect,
+			camera,
+			mask: !0,
+		}
+	}
+
+	pub fn with_mask(mut self, mask: u32) -> Self {
+		self.mask = mask;
+		self
+	}
+}